@@ -0,0 +1,85 @@
+//! Compares the cached line lookups of `Buffer` with walking the rope, both
+//! for a buffer that doesn't change and while typing into it.
+//!
+//! Run with `cargo bench -p lapce-core --bench line_starts`.
+
+use std::time::{Duration, Instant};
+
+use lapce_core::{
+    buffer::{rope_text::RopeText, Buffer},
+    editor::EditType,
+    selection::Selection,
+};
+
+const LINES: usize = 100_000;
+const LOOKUPS: usize = 100_000;
+const KEYSTROKES: usize = 2_000;
+
+fn time(name: &str, f: impl FnOnce() -> usize) {
+    let start = Instant::now();
+    let checksum = f();
+    let elapsed: Duration = start.elapsed();
+    println!("{name:<32} {elapsed:>12.2?}  (checksum {checksum})");
+}
+
+/// Spread the offsets over the whole buffer, in no particular order.
+fn offsets(len: usize, count: usize) -> impl Iterator<Item = usize> {
+    (0..count).map(move |i| i.wrapping_mul(2_654_435_761) % len)
+}
+
+fn main() {
+    let text = (0..LINES)
+        .map(|i| format!("line {i} with some text\n"))
+        .collect::<String>();
+
+    let buffer = Buffer::new(&text);
+    let rope_text = RopeText::new(buffer.text());
+    for offset in offsets(buffer.len(), 1_000) {
+        assert_eq!(
+            buffer.line_of_offset(offset),
+            rope_text.line_of_offset(offset)
+        );
+    }
+
+    time("line_of_offset, cached", || {
+        offsets(buffer.len(), LOOKUPS)
+            .map(|offset| buffer.line_of_offset(offset))
+            .sum()
+    });
+    time("line_of_offset, rope", || {
+        offsets(buffer.len(), LOOKUPS)
+            .map(|offset| rope_text.line_of_offset(offset))
+            .sum()
+    });
+    time("offset_of_line, cached", || {
+        offsets(LINES, LOOKUPS)
+            .map(|line| buffer.offset_of_line(line))
+            .sum()
+    });
+    time("offset_of_line, rope", || {
+        offsets(LINES, LOOKUPS)
+            .map(|line| rope_text.offset_of_line(line))
+            .sum()
+    });
+
+    // Typing in the middle of the buffer, with a lookup after each key, the
+    // way the cursor is updated after every edit
+    let typing = |lookup: fn(&Buffer, usize) -> usize| {
+        let mut buffer = Buffer::new(&text);
+        let mut offset = buffer.len() / 2;
+        let mut checksum = 0;
+        for i in 0..KEYSTROKES {
+            let key = if i % 20 == 19 { "\n" } else { "x" };
+            buffer.edit(&[(Selection::caret(offset), key)], EditType::InsertChars);
+            offset += 1;
+            checksum += lookup(&buffer, offset);
+        }
+        checksum
+    };
+    time("typing, cached", || {
+        typing(|buffer, offset| buffer.line_of_offset(offset))
+    });
+    time("typing, rope", || {
+        typing(|buffer, offset| RopeText::new(buffer.text()).line_of_offset(offset))
+    });
+}
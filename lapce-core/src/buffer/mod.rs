@@ -10,6 +10,9 @@
 };
 
 use lsp_types::Position;
+use once_cell::sync::OnceCell;
+use unicode_bidi::BidiInfo;
+use unicode_width::UnicodeWidthChar;
 use xi_rope::{
     diff::{Diff, LineHashDiff},
     interval::IntervalBounds,
@@ -19,12 +22,12 @@
 };
 
 use crate::{
-    cursor::CursorMode,
+    cursor::{ColPosition, CursorMode},
     editor::EditType,
     indent::{auto_detect_indent_style, IndentStyle},
     mode::Mode,
     selection::Selection,
-    syntax::Syntax,
+    syntax::{util::matching_pair_direction, Syntax},
     word::WordCursor,
 };
 
@@ -32,6 +35,23 @@
 
 use rope_text::*;
 
+/// The line ending used by a text, so that new lines can be inserted the way
+/// the rest of the text has them.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+}
+
+impl LineEnding {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+        }
+    }
+}
+
 #[derive(Clone)]
 enum Contents {
     Edit {
@@ -94,6 +114,10 @@ pub struct Buffer {
 
     max_len: usize,
     max_len_line: usize,
+
+    /// The start offset of every line, plus the text length for the line
+    /// after the last one. Built on first use and updated on every edit.
+    line_starts: OnceCell<Vec<usize>>,
 }
 
 impl ToString for Buffer {
@@ -135,6 +159,8 @@ pub fn new(text: &str) -> Self {
 
             max_len: 0,
             max_len_line: 0,
+
+            line_starts: OnceCell::new(),
         }
     }
 
@@ -290,6 +316,17 @@ pub fn indent_unit(&self) -> &'static str {
         self.indent_style.as_str()
     }
 
+    /// The line ending of the first line, or `\n` if there is only one line.
+    /// The motions treat a `\r\n` as a single boundary either way, so the
+    /// caret never ends up between the `\r` and the `\n`.
+    pub fn line_ending(&self) -> LineEnding {
+        if self.num_lines() > 1 && self.line_content(0).ends_with("\r\n") {
+            LineEnding::CrLf
+        } else {
+            LineEnding::Lf
+        }
+    }
+
     pub fn reset_edit_type(&mut self) {
         self.last_edit_type = EditType::Other;
     }
@@ -324,6 +361,20 @@ pub fn edit(
         self.add_delta(delta)
     }
 
+    /// Build a single delta that replaces each range in `edits` with its
+    /// text. The ranges have to be sorted and must not overlap.
+    pub fn build_delta(&self, edits: &[(Range<usize>, String)]) -> RopeDelta {
+        assert!(
+            edits.windows(2).all(|w| w[0].0.end <= w[1].0.start),
+            "edits must be sorted and must not overlap"
+        );
+        let mut builder = DeltaBuilder::new(self.len());
+        for (range, text) in edits {
+            builder.replace(range.clone(), Rope::from(text));
+        }
+        builder.build()
+    }
+
     fn add_delta(&mut self, delta: RopeDelta) -> (RopeDelta, InvalLines) {
         let undo_group = self.calculate_undo_group();
         self.last_edit_type = self.this_edit_type;
@@ -357,6 +408,7 @@ fn apply_edit(
 
         self.revs.push(new_rev);
         self.text = new_text;
+        self.update_line_starts(iv, newlen);
         self.tombstones = new_tombstones;
         self.deletes_from_union = new_deletes_from_union;
 
@@ -685,8 +737,68 @@ pub fn last_line(&self) -> usize {
         RopeText::new(&self.text).last_line()
     }
 
+    pub fn effective_last_line(&self) -> usize {
+        RopeText::new(&self.text).effective_last_line()
+    }
+
+    /// The last line that line motions can go to in `mode`, which is the
+    /// `effective_last_line`, except in Insert mode where the empty line
+    /// after a trailing newline can be edited.
+    pub fn motion_last_line(&self, mode: Mode) -> usize {
+        if mode == Mode::Insert {
+            self.last_line()
+        } else {
+            self.effective_last_line()
+        }
+    }
+
+    /// The line `delta` lines below `line`, or above it when negative,
+    /// clamped to the lines that line motions can go to in `mode`.
+    pub fn relative_line(&self, line: usize, delta: isize, mode: Mode) -> usize {
+        if delta < 0 {
+            line.saturating_sub(delta.unsigned_abs())
+        } else {
+            line.saturating_add(delta as usize)
+                .min(self.motion_last_line(mode).max(line))
+        }
+    }
+
+    fn line_starts(&self) -> &[usize] {
+        self.line_starts.get_or_init(|| {
+            let mut starts = vec![0];
+            starts.extend(line_starts_in(&self.text, 0, self.text.len()));
+            starts.push(self.text.len());
+            starts
+        })
+    }
+
+    /// Update the cached line starts after `iv` of the old text was replaced
+    /// by `newlen` bytes, which are already in `self.text`. Only the lines
+    /// of the edit are scanned, the ones after it are shifted.
+    fn update_line_starts(&mut self, iv: Interval, newlen: usize) {
+        let line_starts = match self.line_starts.get_mut() {
+            Some(line_starts) => line_starts,
+            None => return,
+        };
+        // The text length, which is pushed back below
+        line_starts.pop();
+        // A line starts inside the edit when the newline before it is inside
+        let first_removed = line_starts.partition_point(|start| *start <= iv.start);
+        let after_removed = line_starts.partition_point(|start| *start <= iv.end);
+        let shift = newlen as isize - (iv.end - iv.start) as isize;
+        for start in &mut line_starts[after_removed..] {
+            *start = (*start as isize + shift) as usize;
+        }
+        line_starts.splice(
+            first_removed..after_removed,
+            line_starts_in(&self.text, iv.start, iv.start + newlen),
+        );
+        line_starts.push(self.text.len());
+    }
+
     pub fn offset_of_line(&self, line: usize) -> usize {
-        RopeText::new(&self.text).offset_of_line(line)
+        let line_starts = self.line_starts();
+        line_starts[line.min(line_starts.len() - 1)]
     }
 
     pub fn offset_line_end(&self, offset: usize, caret: bool) -> usize {
@@ -694,7 +806,13 @@ pub fn offset_line_end(&self, offset: usize, caret: bool) -> usize {
     }
 
     pub fn line_of_offset(&self, offset: usize) -> usize {
-        RopeText::new(&self.text).line_of_offset(offset)
+        let line_starts = self.line_starts();
+        let offset = offset.min(self.len());
+        // The final entry is the text length, which is not a line start unless
+        // the text ends with a newline, in which case it's duplicated.
+        line_starts[..line_starts.len() - 1]
+            .partition_point(|start| *start <= offset)
+            - 1
     }
 
     /// Converts a UTF8 offset to a UTF16 LSP position
@@ -714,7 +832,9 @@ pub fn position_to_line_col(&self, pos: &Position) -> (usize, Option<usize>) {
     }
 
     pub fn offset_to_line_col(&self, offset: usize) -> (usize, usize) {
-        RopeText::new(&self.text).offset_to_line_col(offset)
+        let offset = offset.min(self.len());
+        let line = self.line_of_offset(offset);
+        (line, offset - self.offset_of_line(line))
     }
 
     pub fn offset_of_line_col(&self, line: usize, col: usize) -> usize {
@@ -729,10 +849,117 @@ pub fn first_non_blank_character_on_line(&self, line: usize) -> usize {
         RopeText::new(&self.text).first_non_blank_character_on_line(line)
     }
 
+    /// The first non-blank character on `line`, for the motions that go
+    /// there. A line of only whitespace has none, so this is its last
+    /// column in `mode` instead.
+    pub fn first_non_blank_clamped(&self, line: usize, mode: Mode) -> usize {
+        self.first_non_blank_character_on_line(line)
+            .min(self.line_end_offset(line, mode != Mode::Normal))
+    }
+
+    /// The display column of `offset` in its line, where wide chars like
+    /// CJK ones take up two columns.
+    pub fn display_col_of_offset(&self, offset: usize) -> usize {
+        let line_start = self.offset_of_line(self.line_of_offset(offset));
+        self.slice_to_cow(line_start..offset)
+            .chars()
+            .map(char_display_width)
+            .sum()
+    }
+
+    /// Same as [`Buffer::display_col_of_offset`], but with tabs going on to
+    /// the next multiple of `tab_width` columns, the way they are shown.
+    pub fn visual_col_of_offset(&self, offset: usize, tab_width: usize) -> usize {
+        let line_start = self.offset_of_line(self.line_of_offset(offset));
+        self.slice_to_cow(line_start..offset)
+            .chars()
+            .fold(0, |col, c| match c {
+                '\t' if tab_width > 0 => (col / tab_width + 1) * tab_width,
+                c => col + char_display_width(c),
+            })
+    }
+
+    /// The offset of the char at display column `col` in `line`, which is
+    /// the start of a wide char if `col` is in the middle of one. Columns
+    /// past the end of the line are clamped to it.
+    pub fn offset_of_line_display_col(&self, line: usize, col: usize) -> usize {
+        let line_start = self.offset_of_line(line);
+        let line_end = self.line_end_offset(line, true);
+        let mut width = 0;
+        for (i, c) in self.slice_to_cow(line_start..line_end).char_indices() {
+            width += char_display_width(c);
+            if width > col {
+                return line_start + i;
+            }
+        }
+        line_end
+    }
+
+    /// The column on `line` that `horiz` points to, where `ColPosition::Col`
+    /// holds a column rather than a pixel position.
+    pub fn line_horiz_col(
+        &self,
+        line: usize,
+        horiz: &ColPosition,
+        caret: bool,
+    ) -> usize {
+        match *horiz {
+            ColPosition::Col(col) => {
+                (col as usize).min(self.line_end_col(line, caret))
+            }
+            ColPosition::End => self.line_end_col(line, caret),
+            ColPosition::Start => 0,
+            ColPosition::FirstNonBlank => {
+                let non_blank = self.first_non_blank_character_on_line(line)
+                    - self.offset_of_line(line);
+                non_blank.min(self.line_end_col(line, caret))
+            }
+        }
+    }
+
     pub fn indent_on_line(&self, line: usize) -> String {
         RopeText::new(&self.text).indent_on_line(line)
     }
 
+    /// The first and last line of the block around `line` whose lines are
+    /// indented at least as deep as `line`. Blank lines are part of the block
+    /// only when there are lines of the block after them.
+    pub fn indent_block(&self, line: usize) -> (usize, usize) {
+        let indent = |line: usize| {
+            if self.line_content(line).trim().is_empty() {
+                None
+            } else {
+                Some(self.indent_on_line(line).len())
+            }
+        };
+
+        let level = match indent(line) {
+            Some(level) => level,
+            None => return (line, line),
+        };
+        let in_block = |line: usize| indent(line).map(|indent| indent >= level);
+
+        let mut first = line;
+        for line in (0..line).rev() {
+            match in_block(line) {
+                Some(true) => first = line,
+                Some(false) => break,
+                None => (),
+            }
+        }
+
+        let mut last = line;
+        for line in line + 1..=self.last_line() {
+            match in_block(line) {
+                Some(true) => last = line,
+                Some(false) => break,
+                None => (),
+            }
+        }
+
+        (first, last)
+    }
+
     pub fn line_end_offset(&self, line: usize, caret: bool) -> usize {
         RopeText::new(&self.text).line_end_offset(line, caret)
     }
@@ -768,6 +995,89 @@ pub fn move_left(&self, offset: usize, mode: Mode, count: usize) -> usize {
         self.prev_grapheme_offset(offset, count, min_offset)
     }
 
+    /// Move `count` graphemes to the right, or to the left without `right`, in
+    /// the order the line is displayed in rather than the order of the text,
+    /// so that moving right through a right-to-left run goes back in the
+    /// text. The cursor stays on its line, and lines without right-to-left
+    /// text move like [`Buffer::move_right`] and [`Buffer::move_left`].
+    pub fn move_visual(
+        &self,
+        offset: usize,
+        mode: Mode,
+        count: usize,
+        right: bool,
+    ) -> usize {
+        let logical = || {
+            if right {
+                self.move_right(offset, mode, count)
+            } else {
+                self.move_left(offset, mode, count)
+            }
+        };
+
+        let line = self.line_of_offset(offset);
+        let line_start = self.offset_of_line(line);
+        let line_end = self.line_end_offset(line, true);
+        let content = self.slice_to_cow(line_start..line_end);
+        let bidi = BidiInfo::new(&content, None);
+        let para = match bidi.paragraphs.first() {
+            Some(para) if bidi.has_rtl() => para,
+            _ => return logical(),
+        };
+
+        // The start of every grapheme on the line, in display order
+        let (levels, runs) = bidi.visual_runs(para, para.range.clone());
+        let mut positions = Vec::new();
+        for run in runs {
+            let run_end = line_start + run.end;
+            let mut starts = Vec::new();
+            let mut start = line_start + run.start;
+            while start < run_end {
+                starts.push(start);
+                start = self.next_grapheme_offset(start, 1, run_end);
+            }
+            if levels[run.start].is_rtl() {
+                starts.reverse();
+            }
+            positions.extend(starts);
+        }
+        if mode != Mode::Normal {
+            positions.push(line_end);
+        }
+
+        let ix = match positions.iter().position(|position| *position == offset) {
+            Some(ix) => ix,
+            None => return logical(),
+        };
+        let ix = if right {
+            ix.saturating_add(count).min(positions.len() - 1)
+        } else {
+            ix.saturating_sub(count)
+        };
+        positions[ix]
+    }
+
+    /// Move left by up to `count` graphemes without leaving the line, returning
+    /// the new offset and how many of the `count` were used. Whatever is left
+    /// over can be carried onto the previous line by a wrapping motion.
+    pub fn left_within_line(&self, offset: usize, count: usize) -> (usize, usize) {
+        let line_start = self.offset_of_line(self.line_of_offset(offset));
+        let mut cursor = Cursor::new(&self.text, offset);
+        let mut new_offset = offset;
+        let mut consumed = 0;
+        while consumed < count {
+            match cursor.prev_grapheme() {
+                Some(prev_offset) if prev_offset >= line_start => {
+                    new_offset = prev_offset;
+                    cursor.set(prev_offset);
+                    consumed += 1;
+                }
+                _ => break,
+            }
+        }
+        (new_offset, consumed)
+    }
+
     pub fn move_right(&self, offset: usize, mode: Mode, count: usize) -> usize {
         let max_offset = if mode == Mode::Insert {
             self.len()
@@ -786,6 +1096,31 @@ pub fn move_word_backward(&self, offset: usize) -> usize {
         self.move_n_words_backward(offset, 1)
     }
 
+    /// The grapheme boundary at or before `offset`, which is the start of the
+    /// grapheme `offset` is in the middle of, if it is.
+    pub fn prev_grapheme_boundary(&self, offset: usize) -> usize {
+        let offset = offset.min(self.len());
+        let offset = self.text.at_or_prev_codepoint_boundary(offset).unwrap_or(0);
+        let prev = self.prev_grapheme_offset(offset, 1, 0);
+        let next = self.next_grapheme_offset(prev, 1, self.len());
+        if next <= offset {
+            next
+        } else {
+            prev
+        }
+    }
+
+    /// The grapheme boundary at or after `offset`, which is the end of the
+    /// grapheme `offset` is in the middle of, if it is.
+    pub fn next_grapheme_boundary(&self, offset: usize) -> usize {
+        let prev = self.prev_grapheme_boundary(offset);
+        if prev == offset {
+            offset
+        } else {
+            self.next_grapheme_offset(prev, 1, self.len())
+        }
+    }
+
     pub fn next_grapheme_offset(
         &self,
         offset: usize,
@@ -843,6 +1178,103 @@ pub fn previous_unmatched(
         }
     }
 
+    /// Find the first bracket at or after `offset` on its line, like `%` in
+    /// Vim does when the cursor isn't on a bracket. With `look_behind`, the
+    /// last bracket before `offset` is used if there is none after it.
+    pub fn find_bracket_on_line(
+        &self,
+        offset: usize,
+        look_behind: bool,
+    ) -> Option<usize> {
+        let line = self.line_of_offset(offset);
+        let line_start = self.offset_of_line(line);
+        let line_end = self.line_end_offset(line, true);
+        let is_bracket =
+            |(_, c): &(usize, char)| matching_pair_direction(*c).is_some();
+        let after = self.slice_to_cow(offset..line_end);
+        if let Some((i, _)) = after.char_indices().find(is_bracket) {
+            return Some(offset + i);
+        }
+        if !look_behind {
+            return None;
+        }
+        let before = self.slice_to_cow(line_start..offset);
+        let (i, _) = before.char_indices().rev().find(is_bracket)?;
+        Some(line_start + i)
+    }
+
+    /// Find the quotes enclosing `offset` on its line, as the offsets of the
+    /// opening and the closing quote. Quotes escaped by a backslash inside a
+    /// quoted span don't end it.
+    pub fn find_enclosing_quotes(&self, offset: usize) -> Option<(usize, usize)> {
+        let line = self.line_of_offset(offset);
+        let line_start = self.offset_of_line(line);
+        let line_end = self.line_end_offset(line, true);
+
+        let mut open: Option<(char, usize)> = None;
+        let mut escaped = false;
+        for (i, c) in self.char_indices_iter(line_start..line_end) {
+            let i = line_start + i;
+            if escaped {
+                escaped = false;
+                continue;
+            }
+            match (open, c) {
+                (Some(_), '\\') => escaped = true,
+                (None, '"' | '\'' | '`') => {
+                    if i > offset {
+                        return None;
+                    }
+                    open = Some((c, i));
+                }
+                (Some((quote, start)), c) if c == quote => {
+                    if offset <= i {
+                        return Some((start, i));
+                    }
+                    open = None;
+                }
+                _ => (),
+            }
+        }
+        None
+    }
+
+    /// The sentences of the paragraphs that `start..end` touches, as offset
+    /// ranges without the whitespace around them. A sentence ends after a
+    /// `.`, `!` or `?` that is followed by whitespace, and at a blank line.
+    pub fn sentences_around(&self, start: usize, end: usize) -> Vec<(usize, usize)> {
+        let is_blank = |line: usize| self.line_content(line).trim().is_empty();
+        let last_line = self.last_line();
+
+        let mut first_line = self.line_of_offset(start);
+        while first_line > 0 && !is_blank(first_line - 1) {
+            first_line -= 1;
+        }
+        let mut end_line = self.line_of_offset(end);
+        while end_line < last_line && !is_blank(end_line + 1) {
+            end_line += 1;
+        }
+
+        let mut sentences = Vec::new();
+        let mut paragraph_start = None;
+        for line in first_line..=end_line + 1 {
+            if line <= end_line && !is_blank(line) {
+                paragraph_start.get_or_insert(line);
+                continue;
+            }
+            if let Some(paragraph_start) = paragraph_start.take() {
+                let offset = self.offset_of_line(paragraph_start);
+                let text = self.slice_to_cow(offset..self.offset_of_line(line));
+                sentences.extend(
+                    sentences_in(&text)
+                        .into_iter()
+                        .map(|(start, end)| (offset + start, offset + end)),
+                );
+            }
+        }
+        sentences
+    }
+
     pub fn slice_to_cow(&self, range: Range<usize>) -> Cow<str> {
         self.text
             .slice_to_cow(range.start.min(self.len())..range.end.min(self.len()))
@@ -868,8 +1300,18 @@ pub fn len(&self) -> usize {
     /// search.  The `find_next` function should return None when there is no
     /// more word found.  Despite the name, `find_next` can search in either
     /// direction.
-    fn find_nth_word<F>(
-        &self,
+    fn find_nth_word<F>(&self, offset: usize, count: usize, find_next: F) -> usize
+    where
+        F: FnMut(&mut WordCursor) -> Option<usize>,
+    {
+        let cursor = WordCursor::new(self.text(), offset);
+        Self::find_nth_word_with(cursor, offset, count, find_next)
+    }
+
+    /// Same as [`Buffer::find_nth_word`], with a `cursor` set up by the
+    /// caller, which must be at `offset`.
+    fn find_nth_word_with<F>(
+        mut cursor: WordCursor,
         offset: usize,
         mut count: usize,
         mut find_next: F,
@@ -877,11 +1319,15 @@ fn find_nth_word<F>(
     where
         F: FnMut(&mut WordCursor) -> Option<usize>,
     {
-        let mut cursor = WordCursor::new(self.text(), offset);
         let mut new_offset = offset;
         while count != 0 {
             // FIXME: wait for if-let-chain
             if let Some(offset) = find_next(&mut cursor) {
+                // Stop once the search no longer advances, so that large
+                // counts don't keep looping at the buffer boundary.
+                if offset == new_offset {
+                    break;
+                }
                 new_offset = offset;
             } else {
                 break;
@@ -895,6 +1341,37 @@ pub fn move_n_words_forward(&self, offset: usize, count: usize) -> usize {
         self.find_nth_word(offset, count, |cursor| cursor.next_boundary())
     }
 
+    /// Same as [`Buffer::move_n_words_forward`], but with punctuation taken
+    /// as whitespace if `punctuation_as_space` is set, so that `foo.bar`
+    /// moves from `foo` right to `bar`.
+    pub fn move_n_words_forward_with(
+        &self,
+        offset: usize,
+        count: usize,
+        punctuation_as_space: bool,
+    ) -> usize {
+        let cursor = WordCursor::new(self.text(), offset)
+            .punctuation_as_space(punctuation_as_space);
+        Self::find_nth_word_with(cursor, offset, count, |cursor| {
+            cursor.next_boundary()
+        })
+    }
+
+    /// Same as [`Buffer::move_n_words_backward`], but with punctuation taken
+    /// as whitespace if `punctuation_as_space` is set.
+    pub fn move_n_words_backward_with(
+        &self,
+        offset: usize,
+        count: usize,
+        punctuation_as_space: bool,
+    ) -> usize {
+        let cursor = WordCursor::new(self.text(), offset)
+            .punctuation_as_space(punctuation_as_space);
+        Self::find_nth_word_with(cursor, offset, count, |cursor| {
+            cursor.prev_boundary()
+        })
+    }
+
     pub fn move_n_wordends_forward(
         &self,
         offset: usize,
@@ -912,6 +1389,104 @@ pub fn move_n_wordends_forward(
     pub fn move_n_words_backward(&self, offset: usize, count: usize) -> usize {
         self.find_nth_word(offset, count, |cursor| cursor.prev_boundary())
     }
+
+    pub fn move_n_subwords_forward(&self, offset: usize, count: usize) -> usize {
+        self.find_nth_word(offset, count, |cursor| cursor.next_subword_boundary())
+    }
+
+    pub fn move_n_subwords_backward(&self, offset: usize, count: usize) -> usize {
+        self.find_nth_word(offset, count, |cursor| cursor.prev_subword_boundary())
+    }
+
+    /// Move to the start of the `count`th next subword, going on to the next
+    /// words like [`Buffer::move_n_words_forward`] does.
+    pub fn subword_forward(&self, offset: usize, count: usize) -> usize {
+        self.find_nth_word(offset, count, |cursor| cursor.next_subword_start())
+    }
+
+    /// Move to the start of the `count`th previous subword, going back to the
+    /// previous words like [`Buffer::move_n_words_backward`] does.
+    pub fn subword_backward(&self, offset: usize, count: usize) -> usize {
+        self.find_nth_word(offset, count, |cursor| cursor.prev_subword_start())
+    }
+
+    pub fn move_n_path_segments_forward(
+        &self,
+        offset: usize,
+        count: usize,
+        separators: &[char],
+    ) -> usize {
+        self.find_nth_word(offset, count, |cursor| {
+            cursor.next_path_segment(separators)
+        })
+    }
+
+    pub fn move_n_path_segments_backward(
+        &self,
+        offset: usize,
+        count: usize,
+        separators: &[char],
+    ) -> usize {
+        self.find_nth_word(offset, count, |cursor| {
+            cursor.prev_path_segment(separators)
+        })
+    }
+}
+
+/// The sentences in a paragraph of `text`, see [`Buffer::sentences_around`].
+fn sentences_in(text: &str) -> Vec<(usize, usize)> {
+    let mut sentences = Vec::new();
+    let mut start = None;
+    let mut end = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if c.is_whitespace() {
+            continue;
+        }
+        start.get_or_insert(i);
+        end = i + c.len_utf8();
+        if matches!(c, '.' | '!' | '?') {
+            // Closing quotes and brackets still belong to the sentence
+            while let Some(&(i, c)) = chars.peek() {
+                if !matches!(c, '.' | '!' | '?' | ')' | ']' | '"' | '\'') {
+                    break;
+                }
+                end = i + c.len_utf8();
+                chars.next();
+            }
+            let at_end = match chars.peek() {
+                Some((_, c)) => c.is_whitespace(),
+                None => true,
+            };
+            if at_end {
+                if let Some(start) = start.take() {
+                    sentences.push((start, end));
+                }
+            }
+        }
+    }
+    if let Some(start) = start {
+        sentences.push((start, end));
+    }
+    sentences
+}
+
+/// The offsets after each newline in `start..end` of `text`, which are the
+/// starts of the lines that follow them.
+fn line_starts_in(text: &Rope, start: usize, end: usize) -> Vec<usize> {
+    let mut starts = Vec::new();
+    let mut chunk_offset = start;
+    for chunk in text.iter_chunks(start..end) {
+        starts.extend(
+            chunk
+                .bytes()
+                .enumerate()
+                .filter(|(_, b)| *b == b'\n')
+                .map(|(i, _)| chunk_offset + i + 1),
+        );
+        chunk_offset += chunk.len();
+    }
+    starts
 }
 
 fn shuffle_tombstones(
@@ -1205,5 +1780,11 @@ fn next(&mut self) -> Option<Self::Item> {
     }
 }
 
+/// The number of columns `c` takes up when displayed. Control chars like
+/// tabs have no width of their own, so they count as one column.
+fn char_display_width(c: char) -> usize {
+    c.width().unwrap_or(1)
+}
+
 #[cfg(test)]
 mod test;
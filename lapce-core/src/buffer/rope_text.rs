@@ -29,6 +29,23 @@ pub fn last_line(&self) -> usize {
         self.line_of_offset(self.len())
     }
 
+    /// The last line the cursor can be moved to by line motions, like
+    /// `Down`, `G` and `{count}G`, so that they all agree.
+    ///
+    /// When the text ends with a newline, the empty line after it is not
+    /// counted, the same as in Vim. The cursor can still get there by other
+    /// means, like typing the newline, and line motions don't move it up
+    /// from there. Insert mode, where that line can be typed on, uses
+    /// `last_line` instead.
+    pub fn effective_last_line(&self) -> usize {
+        let last_line = self.last_line();
+        if last_line > 0 && self.offset_of_line(last_line) == self.len() {
+            last_line - 1
+        } else {
+            last_line
+        }
+    }
+
     pub fn offset_of_line(&self, line: usize) -> usize {
         let last_line = self.last_line();
         let line = line.min(last_line + 1);
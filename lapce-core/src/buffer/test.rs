@@ -15,6 +15,140 @@ fn is_pristine() {
         buffer.do_undo();
         assert!(buffer.is_pristine());
     }
+
+    #[test]
+    fn build_delta() {
+        let buffer = Buffer::new("hello world");
+        let delta = buffer.build_delta(&[
+            (0..1, "J".to_string()),
+            (5..5, ",".to_string()),
+            (6..11, "there".to_string()),
+            (11..11, "!".to_string()),
+        ]);
+        assert_eq!("Jello, there!", String::from(delta.apply(buffer.text())));
+
+        let delta = buffer.build_delta(&[]);
+        assert_eq!("hello world", String::from(delta.apply(buffer.text())));
+    }
+
+    #[test]
+    #[should_panic]
+    fn build_delta_rejects_overlapping_edits() {
+        let buffer = Buffer::new("hello world");
+        buffer.build_delta(&[(0..5, "a".to_string()), (4..6, "b".to_string())]);
+    }
+}
+
+mod lines {
+    use super::*;
+    use crate::{
+        buffer::{rope_text::RopeText, LineEnding},
+        editor::EditType,
+        selection::{SelRegion, Selection},
+    };
+
+    fn assert_agrees_with_rope(buffer: &Buffer) {
+        let rope_text = RopeText::new(buffer.text());
+        for offset in 0..buffer.len() + 2 {
+            assert_eq!(
+                buffer.line_of_offset(offset),
+                rope_text.line_of_offset(offset)
+            );
+            assert_eq!(
+                buffer.offset_to_line_col(offset),
+                rope_text.offset_to_line_col(offset)
+            );
+        }
+        for line in 0..buffer.last_line() + 3 {
+            assert_eq!(buffer.offset_of_line(line), rope_text.offset_of_line(line));
+        }
+    }
+
+    #[test]
+    fn cached_lines_agree_with_rope() {
+        for text in ["", "\n", "abc", "abc\n", "a\nbé\r\n\ncd", "\n\nx\n"] {
+            assert_agrees_with_rope(&Buffer::new(text));
+        }
+
+        let mut buffer = Buffer::new("abc\ndef");
+        assert_agrees_with_rope(&buffer);
+        buffer.edit(&[(Selection::caret(1), "x\ny\n")], EditType::InsertChars);
+        assert_agrees_with_rope(&buffer);
+        buffer.edit(&[(Selection::region(0, 6), "")], EditType::Delete);
+        assert_agrees_with_rope(&buffer);
+
+        // Edits at several places at once, across line breaks and at the end
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(0));
+        selection.add_region(SelRegion::new(2, 4, None));
+        selection.add_region(SelRegion::caret(buffer.len()));
+        buffer.edit(&[(selection, "\r\n")], EditType::InsertChars);
+        assert_agrees_with_rope(&buffer);
+        buffer.edit(
+            &[(Selection::caret(buffer.len()), "z")],
+            EditType::InsertChars,
+        );
+        assert_agrees_with_rope(&buffer);
+        buffer.do_undo();
+        assert_agrees_with_rope(&buffer);
+        buffer.do_undo();
+        assert_agrees_with_rope(&buffer);
+        buffer.do_redo();
+        assert_agrees_with_rope(&buffer);
+        buffer.edit(
+            &[(Selection::region(0, buffer.len()), "")],
+            EditType::Delete,
+        );
+        assert_agrees_with_rope(&buffer);
+        assert_eq!(buffer.last_line(), 0);
+    }
+
+    #[test]
+    fn line_ending() {
+        assert_eq!(Buffer::new("").line_ending(), LineEnding::Lf);
+        assert_eq!(Buffer::new("a\r").line_ending(), LineEnding::Lf);
+        assert_eq!(Buffer::new("a\nb\r\n").line_ending(), LineEnding::Lf);
+        assert_eq!(Buffer::new("a\r\nb\n").line_ending(), LineEnding::CrLf);
+        assert_eq!(Buffer::new("\r\n").line_ending(), LineEnding::CrLf);
+    }
+
+    #[test]
+    fn crlf_line_end() {
+        let buffer = Buffer::new("ab\r\n\r\ncd");
+        //                      ->01 2 3 4 5 67<-
+        assert_eq!(buffer.line_end_offset(0, true), 2);
+        assert_eq!(buffer.line_end_offset(0, false), 1);
+        assert_eq!(buffer.line_end_offset(1, true), 4);
+        assert_eq!(buffer.line_end_offset(1, false), 4);
+        assert_eq!(buffer.offset_of_line(1), 4);
+        assert_eq!(buffer.offset_of_line(2), 6);
+    }
+
+    #[test]
+    fn indent_block() {
+        let buffer = Buffer::new(
+            "fn a() {\n    if b {\n        c();\n\n        d();\n    }\n\n    e();\n}\nfn f() {}\n",
+        );
+        // 0 fn a() {
+        // 1     if b {
+        // 2         c();
+        // 3
+        // 4         d();
+        // 5     }
+        // 6
+        // 7     e();
+        // 8 }
+        // 9 fn f() {}
+        assert_eq!((2, 4), buffer.indent_block(2));
+        assert_eq!((2, 4), buffer.indent_block(4));
+        assert_eq!((1, 7), buffer.indent_block(1));
+        assert_eq!((1, 7), buffer.indent_block(7));
+        assert_eq!((0, 9), buffer.indent_block(0));
+        assert_eq!((3, 3), buffer.indent_block(3));
+
+        let buffer = Buffer::new("a\n    b\n\nc\n");
+        assert_eq!((1, 1), buffer.indent_block(1));
+    }
 }
 
 mod motion {
@@ -157,4 +291,126 @@ fn v(buf: &Buffer, off: usize, n: usize, end: usize) {
             v(&buffer, 0, 4, 19);
         }
     }
+
+    #[test]
+    fn punctuation_as_space() {
+        let buffer = Buffer::new("foo.bar, baz");
+        //                      ->012345678901<-
+        let forward = |offset, punctuation_as_space| {
+            buffer.move_n_words_forward_with(offset, 1, punctuation_as_space)
+        };
+        let backward = |offset, punctuation_as_space| {
+            buffer.move_n_words_backward_with(offset, 1, punctuation_as_space)
+        };
+
+        let mut offset = 0;
+        for expected in [3, 4, 7, 9, 12] {
+            offset = forward(offset, false);
+            assert_eq!(offset, expected);
+        }
+        for expected in [9, 7, 4, 3, 0] {
+            offset = backward(offset, false);
+            assert_eq!(offset, expected);
+        }
+
+        let mut offset = 0;
+        for expected in [4, 9, 12] {
+            offset = forward(offset, true);
+            assert_eq!(offset, expected);
+        }
+        for expected in [9, 4, 0] {
+            offset = backward(offset, true);
+            assert_eq!(offset, expected);
+        }
+
+        // Without it, the same as the usual word motions
+        for offset in 0..buffer.len() {
+            assert_eq!(forward(offset, false), buffer.move_word_forward(offset));
+            assert_eq!(backward(offset, false), buffer.move_word_backward(offset));
+        }
+    }
+
+    #[test]
+    fn left_within_line() {
+        let buffer = Buffer::new("abc\ndéf");
+        assert_eq!(buffer.left_within_line(3, 2), (1, 2));
+        assert_eq!(buffer.left_within_line(3, 3), (0, 3));
+        assert_eq!(buffer.left_within_line(3, 5), (0, 3));
+        // "é" is two bytes, but a single grapheme
+        assert_eq!(buffer.left_within_line(8, 2), (5, 2));
+        assert_eq!(buffer.left_within_line(8, 5), (4, 3));
+        assert_eq!(buffer.left_within_line(4, 1), (4, 0));
+        assert_eq!(buffer.left_within_line(0, 0), (0, 0));
+    }
+
+    #[test]
+    fn left_and_right_over_graphemes() {
+        use crate::mode::Mode;
+
+        // The family emoji is three 4 byte emojis joined by two 3 byte ZWJs,
+        // taking up 1..19, and "e\u{301}" is an "e" with a 2 byte combining
+        // accent, taking up 20..23
+        let buffer =
+            Buffer::new("a\u{1F469}\u{200D}\u{1F469}\u{200D}\u{1F467}be\u{301}c");
+        let right = |offset| buffer.move_right(offset, Mode::Insert, 1);
+        let left = |offset| buffer.move_left(offset, Mode::Insert, 1);
+
+        assert_eq!(right(0), 1);
+        assert_eq!(right(1), 19);
+        assert_eq!(right(19), 20);
+        assert_eq!(right(20), 23);
+        assert_eq!(right(23), 24);
+
+        assert_eq!(left(24), 23);
+        assert_eq!(left(23), 20);
+        assert_eq!(left(20), 19);
+        assert_eq!(left(19), 1);
+        assert_eq!(left(1), 0);
+
+        assert_eq!(buffer.move_right(0, Mode::Insert, 3), 20);
+        assert_eq!(buffer.move_left(23, Mode::Normal, 3), 1);
+    }
+
+    #[test]
+    fn move_visual_through_rtl() {
+        use crate::mode::Mode;
+
+        // "abc " then "سلام" (8 bytes, at 4..12, displayed as "مالس") then " d"
+        let buffer = Buffer::new("abc سلام d\nx");
+        let right =
+            |offset, count| buffer.move_visual(offset, Mode::Normal, count, true);
+        let left =
+            |offset, count| buffer.move_visual(offset, Mode::Normal, count, false);
+
+        // Into the right-to-left run at its last char, which is on the left
+        assert_eq!(right(3, 1), 10);
+        assert_eq!(right(10, 1), 8);
+        assert_eq!(right(8, 1), 6);
+        assert_eq!(right(6, 1), 4);
+        assert_eq!(right(4, 1), 12);
+        assert_eq!(right(3, 5), 12);
+        assert_eq!(right(12, 1), 13);
+        assert_eq!(right(13, 1), 13);
+
+        assert_eq!(left(12, 1), 4);
+        assert_eq!(left(4, 4), 3);
+        assert_eq!(left(0, 1), 0);
+        // Insert mode can go past the last char, but not onto the next line
+        assert_eq!(buffer.move_visual(13, Mode::Insert, 3, true), 14);
+
+        // Left-to-right lines move like Left and Right
+        assert_eq!(right(15, 1), 15);
+        assert_eq!(buffer.move_visual(15, Mode::Insert, 1, true), 16);
+    }
+
+    #[test]
+    fn huge_count_stops_at_buffer_boundary() {
+        let buffer = Buffer::new("one two three");
+        assert_eq!(buffer.move_n_words_forward(0, usize::MAX), buffer.len());
+        assert_eq!(buffer.move_n_words_backward(buffer.len(), usize::MAX), 0);
+        assert_eq!(
+            buffer.move_n_wordends_forward(0, usize::MAX, true),
+            buffer.len()
+        );
+    }
 }
@@ -102,6 +102,10 @@ pub enum MoveCommand {
     Down,
     #[strum(serialize = "up")]
     Up,
+    #[strum(serialize = "display_down")]
+    DisplayDown,
+    #[strum(serialize = "display_up")]
+    DisplayUp,
     #[strum(serialize = "left")]
     Left,
     #[strum(serialize = "right")]
@@ -110,6 +114,18 @@ pub enum MoveCommand {
     WordBackward,
     #[strum(serialize = "word_forward")]
     WordForward,
+    #[strum(serialize = "path_segment_backward")]
+    PathSegmentBackward,
+    #[strum(serialize = "path_segment_forward")]
+    PathSegmentForward,
+    #[strum(serialize = "intra_word_backward")]
+    IntraWordBackward,
+    #[strum(serialize = "intra_word_forward")]
+    IntraWordForward,
+    #[strum(serialize = "subword_backward")]
+    SubwordBackward,
+    #[strum(serialize = "subword_forward")]
+    SubwordForward,
     #[strum(serialize = "word_end_forward")]
     WordEndForward,
     #[strum(message = "Document Start")]
@@ -122,14 +138,24 @@ pub enum MoveCommand {
     LineEnd,
     #[strum(serialize = "line_start")]
     LineStart,
+    #[strum(serialize = "smart_home")]
+    SmartHome,
+    #[strum(serialize = "display_line_end")]
+    DisplayLineEnd,
+    #[strum(serialize = "display_line_start")]
+    DisplayLineStart,
     #[strum(serialize = "line_start_non_blank")]
     LineStartNonBlank,
+    #[strum(serialize = "line_end_non_blank")]
+    LineEndNonBlank,
     #[strum(serialize = "go_to_line_default_last")]
     GotoLineDefaultLast,
     #[strum(serialize = "go_to_line_default_first")]
     GotoLineDefaultFirst,
     #[strum(serialize = "match_pairs")]
     MatchPairs,
+    #[strum(serialize = "matching_bracket")]
+    MatchingBracket,
     #[strum(serialize = "next_unmatched_right_bracket")]
     NextUnmatchedRightBracket,
     #[strum(serialize = "previous_unmatched_left_bracket")]
@@ -138,6 +164,10 @@ pub enum MoveCommand {
     NextUnmatchedRightCurlyBracket,
     #[strum(serialize = "previous_unmatched_left_curly_bracket")]
     PreviousUnmatchedLeftCurlyBracket,
+    #[strum(serialize = "previous_enclosing_bracket")]
+    PreviousEnclosingBracket,
+    #[strum(serialize = "next_enclosing_bracket")]
+    NextEnclosingBracket,
 }
 
 impl MoveCommand {
@@ -148,27 +178,42 @@ pub fn to_movement(&self, count: Option<usize>) -> Movement {
             Right => Movement::Right,
             Up => Movement::Up,
             Down => Movement::Down,
+            DisplayDown => Movement::DisplayDown,
+            DisplayUp => Movement::DisplayUp,
             DocumentStart => Movement::DocumentStart,
             DocumentEnd => Movement::DocumentEnd,
             LineStart => Movement::StartOfLine,
+            SmartHome => Movement::SmartHome,
+            DisplayLineEnd => Movement::DisplayEndOfLine,
+            DisplayLineStart => Movement::DisplayStartOfLine,
             LineStartNonBlank => Movement::FirstNonBlank,
+            LineEndNonBlank => Movement::LastNonBlank,
             LineEnd => Movement::EndOfLine,
             GotoLineDefaultFirst => match count {
-                Some(n) => Movement::Line(LinePosition::Line(n)),
+                Some(n) => Movement::GotoLine(n),
                 None => Movement::Line(LinePosition::First),
             },
             GotoLineDefaultLast => match count {
-                Some(n) => Movement::Line(LinePosition::Line(n)),
+                Some(n) => Movement::GotoLine(n),
                 None => Movement::Line(LinePosition::Last),
             },
             WordBackward => Movement::WordBackward,
             WordForward => Movement::WordForward,
+            PathSegmentBackward => Movement::PathSegmentBackward(Vec::new()),
+            PathSegmentForward => Movement::PathSegmentForward(Vec::new()),
+            IntraWordBackward => Movement::IntraWordBackward,
+            IntraWordForward => Movement::IntraWordForward,
+            SubwordBackward => Movement::SubwordBackward,
+            SubwordForward => Movement::SubwordForward,
             WordEndForward => Movement::WordEndForward,
             MatchPairs => Movement::MatchPairs,
+            MatchingBracket => Movement::MatchingBracket(false),
             NextUnmatchedRightBracket => Movement::NextUnmatched(')'),
             PreviousUnmatchedLeftBracket => Movement::PreviousUnmatched('('),
             NextUnmatchedRightCurlyBracket => Movement::NextUnmatched('}'),
             PreviousUnmatchedLeftCurlyBracket => Movement::PreviousUnmatched('{'),
+            PreviousEnclosingBracket => Movement::EnclosingBracketOpen,
+            NextEnclosingBracket => Movement::EnclosingBracketClose,
         }
     }
 }
@@ -344,6 +389,8 @@ pub enum MultiSelectionCommand {
     InsertCursorEndOfLine,
     #[strum(serialize = "select_current_line")]
     SelectCurrentLine,
+    #[strum(serialize = "select_inside_quotes")]
+    SelectInsideQuotes,
     #[strum(serialize = "select_all_current")]
     SelectAllCurrent,
     #[strum(serialize = "select_next_current")]
@@ -76,7 +76,11 @@ pub fn is_visual(&self) -> bool {
     pub fn get_mode(&self) -> Mode {
         match &self.mode {
             CursorMode::Normal(_) => Mode::Normal,
-            CursorMode::Visual { .. } => Mode::Visual,
+            CursorMode::Visual { mode, .. } => match mode {
+                VisualMode::Normal => Mode::Visual,
+                VisualMode::Linewise => Mode::VisualLine,
+                VisualMode::Blockwise => Mode::VisualBlock,
+            },
             CursorMode::Insert(_) => Mode::Insert,
         }
     }
@@ -103,6 +107,7 @@ pub fn update_selection(&mut self, buffer: &Buffer, selection: Selection) {
                 self.mode = CursorMode::Insert(selection);
             }
         }
+        self.horiz = None;
     }
 
     pub fn edit_selection(&self, buffer: &Buffer) -> Selection {
@@ -186,25 +191,13 @@ pub fn apply_delta(&mut self, delta: &RopeDelta) {
     pub fn yank(&self, buffer: &Buffer) -> RegisterData {
         let (content, mode) = match &self.mode {
             CursorMode::Insert(selection) => {
-                let mut mode = VisualMode::Normal;
-                let mut content = "".to_string();
-                for region in selection.regions() {
-                    let region_content = if region.is_caret() {
-                        mode = VisualMode::Linewise;
-                        let line = buffer.line_of_offset(region.start);
-                        buffer.line_content(line)
-                    } else {
-                        buffer.slice_to_cow(region.min()..region.max())
-                    };
-                    if content.is_empty() {
-                        content = region_content.to_string();
-                    } else if content.ends_with('\n') {
-                        content += &region_content;
-                    } else {
-                        content += "\n";
-                        content += &region_content;
-                    }
-                }
+                let (content, line_wise) =
+                    selection.clipboard_text(buffer, &Mode::Insert);
+                let mode = if line_wise {
+                    VisualMode::Linewise
+                } else {
+                    VisualMode::Normal
+                };
                 (content, mode)
             }
             CursorMode::Normal(offset) => {
@@ -513,6 +513,9 @@ pub fn do_paste(
                 }
             }
         }
+        // The cursor was moved by the paste, so the column to keep for
+        // vertical movement has to come from where it is now.
+        cursor.horiz = None;
         deltas
     }
 
@@ -980,44 +983,15 @@ pub fn do_edit<T: Clipboard>(
                             EditType::DeleteSelection
                         };
                         let indent = buffer.indent_unit();
+                        let ranges = if indent.starts_with('\t') {
+                            selection.backspace_range(buffer)
+                        } else {
+                            selection.smart_backspace_range(buffer, indent.len())
+                        };
                         let mut new_selection = Selection::new();
-                        for region in selection.regions() {
-                            let new_region = if region.is_caret() {
-                                if indent.starts_with('\t') {
-                                    let new_end = buffer.move_left(
-                                        region.end,
-                                        Mode::Insert,
-                                        1,
-                                    );
-                                    SelRegion::new(region.start, new_end, None)
-                                } else {
-                                    let line = buffer.line_of_offset(region.start);
-                                    let nonblank = buffer
-                                        .first_non_blank_character_on_line(line);
-                                    let (_, col) =
-                                        buffer.offset_to_line_col(region.start);
-                                    let count =
-                                        if region.start <= nonblank && col > 0 {
-                                            let r = col % indent.len();
-                                            if r == 0 {
-                                                indent.len()
-                                            } else {
-                                                r
-                                            }
-                                        } else {
-                                            1
-                                        };
-                                    let new_end = buffer.move_left(
-                                        region.end,
-                                        Mode::Insert,
-                                        count,
-                                    );
-                                    SelRegion::new(region.start, new_end, None)
-                                }
-                            } else {
-                                *region
-                            };
-                            new_selection.add_region(new_region);
+                        for (start, end) in ranges {
+                            new_selection
+                                .add_region(SelRegion::new(start, end, None));
                         }
 
                         let mut selection = new_selection;
@@ -1084,15 +1058,9 @@ pub fn do_edit<T: Clipboard>(
                             EditType::DeleteSelection
                         };
                         let mut new_selection = Selection::new();
-                        for region in selection.regions() {
-                            let new_region = if region.is_caret() {
-                                let new_end =
-                                    buffer.move_right(region.end, Mode::Insert, 1);
-                                SelRegion::new(region.start, new_end, None)
-                            } else {
-                                *region
-                            };
-                            new_selection.add_region(new_region);
+                        for (start, end) in selection.delete_forward_range(buffer) {
+                            new_selection
+                                .add_region(SelRegion::new(start, end, None));
                         }
                         (new_selection, edit_type)
                     }
@@ -1194,12 +1162,10 @@ pub fn do_edit<T: Clipboard>(
                     if let CursorMode::Insert(selection) = &cursor.mode {
                         match selection.regions().len() {
                             i if i > 1 => {
-                                if let Some(region) = selection.last_inserted() {
-                                    let new_selection =
-                                        Selection::region(region.start, region.end);
-                                    cursor.mode = CursorMode::Insert(new_selection);
-                                    return vec![];
-                                }
+                                cursor.mode = CursorMode::Insert(
+                                    selection.collapse_to_primary(),
+                                );
+                                return vec![];
                             }
                             i if i == 1 => {
                                 let region = selection.regions()[0];
@@ -1291,8 +1257,11 @@ pub fn do_edit<T: Clipboard>(
 #[cfg(test)]
 mod test {
     use crate::buffer::Buffer;
-    use crate::cursor::{Cursor, CursorMode};
+    use crate::cursor::{ColPosition, Cursor, CursorMode};
     use crate::editor::Editor;
+    use crate::mode::{Mode, VisualMode};
+    use crate::movement::Movement;
+    use crate::register::RegisterData;
     use crate::selection::{SelRegion, Selection};
 
     #[test]
@@ -1348,4 +1317,96 @@ fn test_insert_pair() {
         Editor::insert(&mut cursor, &mut buffer, "}", None);
         assert_eq!("a{} bc\ne{} fg\n", buffer.slice_to_cow(0..buffer.len()));
     }
+
+    #[test]
+    fn test_paste_resets_horiz() {
+        let mut buffer = Buffer::new("abcd\n0123456789\n");
+        let mut cursor =
+            Cursor::new(CursorMode::Normal(1), Some(ColPosition::Col(1.0)), None);
+
+        let data = RegisterData {
+            content: "hello!".to_string(),
+            mode: VisualMode::Normal,
+        };
+        Editor::do_paste(&mut cursor, &mut buffer, &data);
+        assert_eq!("abhello!cd\n", buffer.line_content(0));
+        assert_eq!(7, cursor.offset());
+        assert_eq!(None, cursor.horiz);
+
+        let (offset, horiz) = Movement::Down.move_offset(
+            &buffer,
+            cursor.offset(),
+            cursor.horiz.as_ref(),
+            1,
+            Mode::Normal,
+        );
+        assert_eq!((1, 7), buffer.offset_to_line_col(offset));
+        assert_eq!(Some(ColPosition::Col(7.0)), horiz);
+    }
+
+    #[test]
+    fn test_visual_word_forward_selects_last_word() {
+        let buffer = Buffer::new("one two three");
+        let mut cursor = Cursor::new(
+            CursorMode::Visual {
+                start: 4,
+                end: 4,
+                mode: VisualMode::Normal,
+            },
+            None,
+            None,
+        );
+
+        // Pressing `w` past the last word keeps the selection at its end.
+        for _ in 0..4 {
+            if let CursorMode::Visual { start, end, mode } = cursor.mode {
+                let (end, _) = Movement::WordForward.move_offset(
+                    &buffer,
+                    end,
+                    None,
+                    1,
+                    Mode::Visual,
+                );
+                cursor.mode = CursorMode::Visual { start, end, mode };
+            }
+        }
+
+        assert_eq!(buffer.len(), cursor.offset());
+        assert_eq!(
+            Selection::region(4, buffer.len()),
+            cursor.edit_selection(&buffer)
+        );
+    }
+
+    #[test]
+    fn test_toggle_visual_mode() {
+        let mut cursor = Cursor::new(CursorMode::Normal(0), None, None);
+
+        Editor::toggle_visual(&mut cursor, VisualMode::Linewise, true);
+        assert_eq!(Mode::VisualLine, cursor.get_mode());
+        Editor::toggle_visual(&mut cursor, VisualMode::Blockwise, true);
+        assert_eq!(Mode::VisualBlock, cursor.get_mode());
+        Editor::toggle_visual(&mut cursor, VisualMode::Normal, true);
+        assert_eq!(Mode::Visual, cursor.get_mode());
+        Editor::toggle_visual(&mut cursor, VisualMode::Normal, true);
+        assert_eq!(Mode::Normal, cursor.get_mode());
+    }
+
+    #[test]
+    fn test_visual_get_mode() {
+        let cursor = |mode| {
+            Cursor::new(
+                CursorMode::Visual {
+                    start: 0,
+                    end: 2,
+                    mode,
+                },
+                None,
+                None,
+            )
+        };
+        assert_eq!(Mode::Visual, cursor(VisualMode::Normal).get_mode());
+        assert_eq!(Mode::VisualLine, cursor(VisualMode::Linewise).get_mode());
+        assert_eq!(Mode::VisualBlock, cursor(VisualMode::Blockwise).get_mode());
+    }
 }
@@ -0,0 +1,37 @@
+use crate::buffer::Buffer;
+
+/// Where logical lines are split into display rows by soft wrapping, so that
+/// movements can follow what is displayed rather than only the text.
+pub trait LineLayout {
+    /// The columns at which the display rows of `line` start. The first row
+    /// starts at column 0, and a line that isn't wrapped only has that row.
+    fn row_starts(&self, buffer: &Buffer, line: usize) -> Vec<usize>;
+}
+
+/// How far across its line each column is shown, which is what vertical
+/// movements keep in `ColPosition::Col` so that the cursor stays in the same
+/// place on screen. The editor measures this on its text layouts, while
+/// [`DisplayColumns`] does without one.
+pub trait LineColumns {
+    /// The horizontal position of `offset` in its line.
+    fn horiz_of_offset(&self, buffer: &Buffer, offset: usize) -> f64;
+
+    /// The column of `line` that is shown closest to the horizontal position
+    /// `horiz`. It can be past the end of the line, which callers clamp.
+    fn col_of_horiz(&self, buffer: &Buffer, line: usize, horiz: f64) -> usize;
+}
+
+/// Display columns as the horizontal position, where wide chars like CJK
+/// ones take up two columns, see [`Buffer::display_col_of_offset`].
+pub struct DisplayColumns;
+
+impl LineColumns for DisplayColumns {
+    fn horiz_of_offset(&self, buffer: &Buffer, offset: usize) -> f64 {
+        buffer.display_col_of_offset(offset) as f64
+    }
+
+    fn col_of_horiz(&self, buffer: &Buffer, line: usize, horiz: f64) -> usize {
+        buffer.offset_of_line_display_col(line, horiz as usize)
+            - buffer.offset_of_line(line)
+    }
+}
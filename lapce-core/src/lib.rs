@@ -6,6 +6,7 @@
 pub mod encoding;
 pub mod indent;
 pub mod language;
+pub mod layout;
 pub mod lens;
 pub mod mode;
 pub mod movement;
@@ -29,6 +29,13 @@ pub enum Mode {
     Normal,
     Insert,
     Visual,
+    /// Line-wise Visual mode, where selections always cover whole lines, like
+    /// `V` in Vim. This is the mode of a cursor in [`VisualMode::Linewise`].
+    VisualLine,
+    /// Block-wise Visual mode, where selections cover the same columns on
+    /// every line, like `Ctrl-V` in Vim. This is the mode of a cursor in
+    /// [`VisualMode::Blockwise`].
+    VisualBlock,
     Terminal,
 }
 
@@ -46,7 +53,7 @@ fn from(mode: Mode) -> Self {
         match mode {
             Mode::Normal => Self::NORMAL,
             Mode::Insert => Self::INSERT,
-            Mode::Visual => Self::VISUAL,
+            Mode::Visual | Mode::VisualLine | Mode::VisualBlock => Self::VISUAL,
             Mode::Terminal => Self::TERMINAL,
         }
     }
@@ -1,29 +1,171 @@
+use std::ops::Range;
+
+use crate::{
+    buffer::Buffer,
+    cursor::ColPosition,
+    layout::{DisplayColumns, LineColumns, LineLayout},
+    mode::Mode,
+    selection::{SelRegion, Selection},
+    word::WordCursor,
+};
+
+/// The largest count a motion is repeated by, larger counts are clamped to
+/// this so that a mistyped or malicious count can't stall the editor.
+pub const MAX_MOTION_COUNT: usize = 1_000_000;
+
+/// The outcome of moving a region, with whether the cursor actually moved so
+/// that callers can give feedback when a motion hits the document edge.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MovementResult {
+    pub region: SelRegion,
+    pub moved: bool,
+}
+
+/// The outcome of moving a region vertically, with whether the line it ended
+/// up on was too short for the remembered column, so that the column can be
+/// shown differently from where the cursor is.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VerticalMoveResult {
+    pub region: SelRegion,
+    pub clamped: bool,
+}
+
 #[derive(Clone, Debug)]
 pub enum LinePosition {
     First,
     Last,
+    /// The zero-based line, unlike the 1-based [`Movement::GotoLine`].
     Line(usize),
+    /// The given number of lines below the cursor, or above it when
+    /// negative, like `:+5` and `:-3` in Vim.
+    Relative(isize),
 }
 
 #[derive(Clone, Debug)]
 pub enum Movement {
     Left,
     Right,
+    /// Left in the order bidirectional text is displayed in, see
+    /// [`Buffer::move_visual`].
+    VisualLeft,
+    /// Right in the order bidirectional text is displayed in, see
+    /// [`Buffer::move_visual`].
+    VisualRight,
     Up,
     Down,
+    /// Up by display rows rather than lines when soft wrapping, like `gk`
+    /// in Vim. See [`Movement::move_offset_with_layout`].
+    DisplayUp,
+    /// Down by display rows rather than lines when soft wrapping, like `gj`
+    /// in Vim. See [`Movement::move_offset_with_layout`].
+    DisplayDown,
+    /// Up by a page of the given number of lines, keeping the column like
+    /// [`Movement::Up`] does.
+    PageUp(usize),
+    /// Down by a page of the given number of lines, keeping the column like
+    /// [`Movement::Down`] does.
+    PageDown(usize),
+    /// Up by half a page of the given number of lines, like `Ctrl-U` in Vim.
+    HalfPageUp(usize),
+    /// Down by half a page of the given number of lines, like `Ctrl-D` in
+    /// Vim.
+    HalfPageDown(usize),
+    /// The very start of the buffer, like `Ctrl-Home` in most editors, with
+    /// `ColPosition::Start` as the column to keep.
     DocumentStart,
+    /// The very end of the buffer, like `Ctrl-End` in most editors, or its
+    /// last char in Normal mode, with `ColPosition::End` as the column to
+    /// keep. Unlike `G` in Vim, which is [`Movement::Line`] with
+    /// [`LinePosition::Last`], this goes to the empty line after a final
+    /// newline.
     DocumentEnd,
     FirstNonBlank,
+    /// The first non-whitespace character of the line, or the start of the
+    /// line when already there, like the `Home` key in most editors.
+    SmartHome,
+    /// The last non-whitespace character of the line, like `g_` in Vim.
+    LastNonBlank,
     StartOfLine,
     EndOfLine,
+    /// The start of the display row when soft wrapping, or of the line when
+    /// already there. See [`Movement::move_offset_with_layout`].
+    DisplayStartOfLine,
+    /// The end of the display row when soft wrapping, or of the line when
+    /// already there. See [`Movement::move_offset_with_layout`].
+    DisplayEndOfLine,
+    /// Go to the zero-based display column on the current line, like `|` in
+    /// Vim, or to the end of the line if it's shorter.
+    ColumnGoto(usize),
     Line(LinePosition),
+    /// Go to the 1-based line, on its first non-blank character, like
+    /// `{count}G` in Vim.
+    GotoLine(usize),
     Offset(usize),
+    /// Go to the first line of the next changed hunk, like `]c` in Vim,
+    /// wrapping around to the first hunk. The hunks are sorted ranges of
+    /// lines.
+    NextChange(Vec<Range<usize>>),
+    /// Go to the first line of the previous changed hunk, like `[c` in Vim,
+    /// wrapping around to the last hunk. The hunks are sorted ranges of
+    /// lines.
+    PrevChange(Vec<Range<usize>>),
+    /// Go to the next line that starts with one of the given folding marker
+    /// comments, like `// region` or `#region`, ignoring indentation.
+    NextFoldMarker(Vec<String>),
+    /// Go to the previous line that starts with one of the given folding
+    /// marker comments, ignoring indentation.
+    PrevFoldMarker(Vec<String>),
     WordEndForward,
     WordForward,
     WordBackward,
+    /// Move to the start of the next path segment, splitting on `/` and the
+    /// given extra separators.
+    PathSegmentForward(Vec<char>),
+    /// Move to the start of the previous path segment, splitting on `/` and
+    /// the given extra separators.
+    PathSegmentBackward(Vec<char>),
+    /// Move to the start of the next camelCase, snake_case or digit part of
+    /// the current word, stopping at the end of the word.
+    IntraWordForward,
+    /// Move to the start of the previous camelCase, snake_case or digit part
+    /// of the current word, stopping at the start of the word.
+    IntraWordBackward,
+    /// Move to the start of the next camelCase, snake_case or digit part,
+    /// going on to the next word at the end of the current one.
+    SubwordForward,
+    /// Move to the start of the previous camelCase, snake_case or digit part,
+    /// going back to the previous word at the start of the current one.
+    SubwordBackward,
+    /// Find `ch` on the current line, like `f`, `F`, `t` and `T` in Vim.
+    /// With `till`, stop at the char just before it, or just after it when
+    /// going backward.
+    FindChar {
+        ch: char,
+        forward: bool,
+        till: bool,
+    },
+    /// Go forward to the nth occurrence of the char after the cursor, going
+    /// on to the next lines if needed. Unlike [`Movement::FindChar`], the
+    /// count is part of the movement. With fewer occurrences, or a count of
+    /// zero, the cursor stays where it is.
+    ToNthChar(char, usize),
     NextUnmatched(char),
     PreviousUnmatched(char),
     MatchPairs,
+    /// Move to the bracket matching the first bracket at or after the cursor
+    /// on its line, like `%` in Vim. If the flag is set and there is no such
+    /// bracket, the last bracket before the cursor on the line is used. The
+    /// match can be on another line, nested pairs of the same kind are
+    /// skipped. Without a match the cursor stays where it is.
+    MatchingBracket(bool),
+    EnclosingBracketOpen,
+    EnclosingBracketClose,
+    /// Move to just after the `{` that opens the enclosing block, like `[{`
+    /// in Vim.
+    ScopeStart,
+    /// Move to just before the `}` that closes the enclosing block, like `]}`
+    /// in Vim.
+    ScopeEnd,
 }
 
 impl PartialEq for Movement {
@@ -38,21 +180,49 @@ pub fn is_vertical(&self) -> bool {
             self,
             Movement::Up
                 | Movement::Down
+                | Movement::DisplayUp
+                | Movement::DisplayDown
+                | Movement::PageUp(_)
+                | Movement::PageDown(_)
+                | Movement::HalfPageUp(_)
+                | Movement::HalfPageDown(_)
                 | Movement::Line(_)
+                | Movement::GotoLine(_)
                 | Movement::DocumentStart
                 | Movement::DocumentEnd
         )
     }
 
     pub fn is_inclusive(&self) -> bool {
-        matches!(self, Movement::WordEndForward)
+        matches!(self, Movement::WordEndForward | Movement::LastNonBlank)
+    }
+
+    /// The line move a page motion is made of, and how many lines a page
+    /// is, or `None` for other motions.
+    pub fn page_step(&self) -> Option<(Movement, usize)> {
+        match self {
+            Movement::PageUp(height) => Some((Movement::Up, *height)),
+            Movement::PageDown(height) => Some((Movement::Down, *height)),
+            Movement::HalfPageUp(height) => {
+                Some((Movement::Up, (height / 2).max(1)))
+            }
+            Movement::HalfPageDown(height) => {
+                Some((Movement::Down, (height / 2).max(1)))
+            }
+            _ => None,
+        }
     }
 
     pub fn is_jump(&self) -> bool {
         matches!(
             self,
             Movement::Line(_)
+                | Movement::GotoLine(_)
                 | Movement::Offset(_)
+                | Movement::NextChange(_)
+                | Movement::PrevChange(_)
+                | Movement::NextFoldMarker(_)
+                | Movement::PrevFoldMarker(_)
                 | Movement::DocumentStart
                 | Movement::DocumentEnd
         )
@@ -83,15 +253,953 @@ pub fn update_index(
                 LinePosition::Line(n) => (*n).min(last),
                 LinePosition::First => 0,
                 LinePosition::Last => last,
+                LinePosition::Relative(delta) if *delta < 0 => {
+                    index.saturating_sub(delta.unsigned_abs())
+                }
+                LinePosition::Relative(delta) => {
+                    index.saturating_add(*delta as usize).min(last)
+                }
             },
+            Movement::GotoLine(n) => n.saturating_sub(1).min(last),
             _ => index,
         }
     }
+
+    /// The line the viewport should scroll to, as its new top line, so that
+    /// at least `scrolloff` lines stay visible around the cursor of `region`
+    /// after a movement. `viewport` is the range of lines that are visible.
+    /// Returns `None` when no scrolling is needed.
+    pub fn scrolloff_target(
+        region: &SelRegion,
+        buffer: &Buffer,
+        viewport: Range<usize>,
+        scrolloff: usize,
+    ) -> Option<usize> {
+        let height = viewport.len();
+        if height == 0 {
+            return None;
+        }
+        // Like Vim, a scrolloff too large for the viewport keeps the cursor
+        // in the middle.
+        let scrolloff = scrolloff.min((height - 1) / 2);
+        let line = buffer.line_of_offset(region.end);
+
+        let top = if line < viewport.start + scrolloff {
+            line.saturating_sub(scrolloff)
+        } else if line + scrolloff >= viewport.end {
+            // Don't scroll past the end of the document to keep the margin
+            let max_top = (buffer.last_line() + 1).saturating_sub(height);
+            (line + scrolloff + 1 - height).min(max_top.max(viewport.start))
+        } else {
+            return None;
+        };
+        (top != viewport.start).then_some(top)
+    }
+
+    /// Move every region of `selection`, see [`Movement::update_region`].
+    pub fn update_selection(
+        &self,
+        selection: &Selection,
+        buffer: &Buffer,
+        count: usize,
+        modify: bool,
+        mode: Mode,
+    ) -> Selection {
+        self.update_selection_with(
+            selection,
+            buffer,
+            count,
+            modify,
+            mode,
+            |offset, horiz, count, mode| {
+                self.move_offset(buffer, offset, horiz, count, mode)
+            },
+        )
+    }
+
+    /// Same as [`Movement::update_selection`], but the cursors are moved by
+    /// `move_offset`, see [`Movement::update_region_with`].
+    pub fn update_selection_with(
+        &self,
+        selection: &Selection,
+        buffer: &Buffer,
+        count: usize,
+        modify: bool,
+        mode: Mode,
+        mut move_offset: impl FnMut(
+            usize,
+            Option<&ColPosition>,
+            usize,
+            Mode,
+        ) -> (usize, Option<ColPosition>),
+    ) -> Selection {
+        if mode == Mode::VisualBlock {
+            return self.update_block_selection(
+                selection,
+                buffer,
+                count,
+                modify,
+                &mut move_offset,
+            );
+        }
+        let mut new_selection = Selection::new();
+        for region in selection.regions() {
+            new_selection.add_region(self.update_region_with(
+                region,
+                buffer,
+                count,
+                modify,
+                mode,
+                &mut move_offset,
+            ));
+        }
+        new_selection
+    }
+
+    /// Move the cursor of a block selection made by [`Selection::from_block`]
+    /// and make the block again from its anchor, or from the cursor alone
+    /// when `modify` is not set. Lines too short for the block are clamped.
+    fn update_block_selection(
+        &self,
+        selection: &Selection,
+        buffer: &Buffer,
+        count: usize,
+        modify: bool,
+        mut move_offset: impl FnMut(
+            usize,
+            Option<&ColPosition>,
+            usize,
+            Mode,
+        ) -> (usize, Option<ColPosition>),
+    ) -> Selection {
+        let cursor = match selection.last_inserted() {
+            Some(region) => *region,
+            None => return selection.clone(),
+        };
+        // The anchor is on the line at the other end of the block
+        let anchor = if selection.first() == Some(&cursor) {
+            selection.last()
+        } else {
+            selection.first()
+        }
+        .map_or(cursor.start, |region| region.start);
+
+        let (end, horiz) =
+            move_offset(cursor.end, cursor.horiz.as_ref(), count, Mode::VisualBlock);
+        let anchor = if modify { anchor } else { end };
+        let mut new_selection = Selection::from_block(buffer, anchor, end, false);
+        if let Some(region) = new_selection.last_inserted_mut() {
+            region.horiz = horiz;
+        }
+        new_selection
+    }
+
+    /// Move the end of `region`, keeping its start when `modify` is set.
+    ///
+    /// This works on the buffer alone: there is no text layout, so
+    /// `ColPosition::Col` holds a display column in the line as returned by
+    /// `Buffer::display_col_of_offset` rather than a pixel position. Wide
+    /// chars like CJK ones take up two columns, so that moving up or down
+    /// keeps the cursor visually aligned.
+    pub fn update_region(
+        &self,
+        region: &SelRegion,
+        buffer: &Buffer,
+        count: usize,
+        modify: bool,
+        mode: Mode,
+    ) -> SelRegion {
+        self.update_region_with(
+            region,
+            buffer,
+            count,
+            modify,
+            mode,
+            |offset, horiz, count, mode| {
+                self.move_offset(buffer, offset, horiz, count, mode)
+            },
+        )
+    }
+
+    /// Same as [`Movement::update_region`], but the cursor is moved by
+    /// `move_offset`, which is given the offset, `horiz`, count and mode to
+    /// move with. The editor moves with its text layouts this way, see
+    /// [`Movement::move_offset_with_columns`].
+    pub fn update_region_with(
+        &self,
+        region: &SelRegion,
+        buffer: &Buffer,
+        count: usize,
+        modify: bool,
+        mode: Mode,
+        mut move_offset: impl FnMut(
+            usize,
+            Option<&ColPosition>,
+            usize,
+            Mode,
+        ) -> (usize, Option<ColPosition>),
+    ) -> SelRegion {
+        if mode == Mode::VisualLine {
+            return self.update_linewise_region(
+                region,
+                buffer,
+                count,
+                modify,
+                move_offset,
+            );
+        }
+        let (end, horiz) =
+            move_offset(region.end, region.horiz.as_ref(), count, mode);
+        let start = match modify {
+            true => region.start(),
+            false => end,
+        };
+        SelRegion::new(start, end, horiz)
+    }
+
+    /// Move the cursor of a line-wise region and expand the region to whole
+    /// lines, from the start of the anchor's line to the end of the cursor's
+    /// line, or the other way around when the cursor is above the anchor.
+    ///
+    /// The expanded region doesn't say where in its line the cursor is, so
+    /// the move starts at the start of the line, relying on `horiz` to keep
+    /// the column for vertical moves. A caret is a region that hasn't been
+    /// expanded yet, so the move starts from the caret itself.
+    fn update_linewise_region(
+        &self,
+        region: &SelRegion,
+        buffer: &Buffer,
+        count: usize,
+        modify: bool,
+        mut move_offset: impl FnMut(
+            usize,
+            Option<&ColPosition>,
+            usize,
+            Mode,
+        ) -> (usize, Option<ColPosition>),
+    ) -> SelRegion {
+        let (anchor_line, cursor_line) = if region.start < region.end {
+            (
+                buffer.line_of_offset(region.start),
+                buffer.line_of_offset(region.end - 1),
+            )
+        } else if region.start > region.end {
+            (
+                buffer.line_of_offset(region.start - 1),
+                buffer.line_of_offset(region.end),
+            )
+        } else {
+            let line = buffer.line_of_offset(region.end);
+            (line, line)
+        };
+        let offset = if region.is_caret() {
+            region.end
+        } else {
+            buffer.offset_of_line(cursor_line)
+        };
+
+        let (end, horiz) =
+            move_offset(offset, region.horiz.as_ref(), count, Mode::VisualLine);
+        let cursor_line = buffer.line_of_offset(end);
+        let anchor_line = if modify { anchor_line } else { cursor_line };
+
+        let (start, end) = if cursor_line >= anchor_line {
+            (
+                buffer.offset_of_line(anchor_line),
+                buffer.offset_of_line(cursor_line + 1),
+            )
+        } else {
+            (
+                buffer.offset_of_line(anchor_line + 1),
+                buffer.offset_of_line(cursor_line),
+            )
+        };
+        SelRegion::new(start, end, horiz)
+    }
+
+    /// Same as [`Movement::update_region`], but also reports whether the end
+    /// of the region moved.
+    pub fn update_region_with_result(
+        &self,
+        region: &SelRegion,
+        buffer: &Buffer,
+        count: usize,
+        modify: bool,
+        mode: Mode,
+    ) -> MovementResult {
+        let new_region = self.update_region(region, buffer, count, modify, mode);
+        MovementResult {
+            moved: new_region.end != region.end,
+            region: new_region,
+        }
+    }
+
+    /// Same as [`Movement::update_region`], but also reports whether the
+    /// column the cursor ended up in is before the remembered column.
+    pub fn update_region_vertical(
+        &self,
+        region: &SelRegion,
+        buffer: &Buffer,
+        count: usize,
+        modify: bool,
+        mode: Mode,
+    ) -> VerticalMoveResult {
+        let region = self.update_region(region, buffer, count, modify, mode);
+        let clamped = match region.horiz {
+            Some(ColPosition::Col(col)) => {
+                (buffer.display_col_of_offset(region.end) as f64) < col
+            }
+            _ => false,
+        };
+        VerticalMoveResult { region, clamped }
+    }
+
+    /// Same as [`Movement::update_region`], but the goal column of vertical
+    /// moves is kept by the caller in `goal_col`, as a display column,
+    /// instead of in the region's `horiz`, which is left empty. Moves that
+    /// don't keep a column, like horizontal ones, clear it, and the next
+    /// vertical move sets it again from where the cursor is.
+    pub fn update_region_with_goal(
+        &self,
+        region: &SelRegion,
+        buffer: &Buffer,
+        count: usize,
+        modify: bool,
+        mode: Mode,
+        goal_col: &mut Option<usize>,
+    ) -> SelRegion {
+        let region = SelRegion::new(
+            region.start,
+            region.end,
+            goal_col.map(|col| ColPosition::Col(col as f64)),
+        );
+        let region = self.update_region(&region, buffer, count, modify, mode);
+        *goal_col = match region.horiz {
+            Some(ColPosition::Col(col)) => Some(col as usize),
+            _ => None,
+        };
+        SelRegion::new(region.start, region.end, None)
+    }
+
+    /// Same as [`Movement::move_offset`], but display row movements follow
+    /// the rows of `layout`. For those, `ColPosition::Col` holds a column in
+    /// the display row instead of in the line.
+    pub fn move_offset_with_layout(
+        &self,
+        buffer: &Buffer,
+        layout: &dyn LineLayout,
+        offset: usize,
+        horiz: Option<&ColPosition>,
+        count: usize,
+        mode: Mode,
+    ) -> (usize, Option<ColPosition>) {
+        let count = count.min(MAX_MOTION_COUNT);
+        match self {
+            Movement::DisplayUp => {
+                move_display_rows(buffer, layout, offset, horiz, count, false, mode)
+            }
+            Movement::DisplayDown => {
+                move_display_rows(buffer, layout, offset, horiz, count, true, mode)
+            }
+            Movement::DisplayStartOfLine => {
+                move_display_row_edge(buffer, layout, offset, false, mode)
+            }
+            Movement::DisplayEndOfLine => {
+                move_display_row_edge(buffer, layout, offset, true, mode)
+            }
+            _ => self.move_offset(buffer, offset, horiz, count, mode),
+        }
+    }
+
+    /// Same as [`Movement::move_offset`], but `Up` and `Down` step over
+    /// folded lines. Each fold is a range of lines whose first line is the
+    /// header that stays visible, so it's a valid place to land, while the
+    /// rest of its lines are hidden and skipped.
+    pub fn move_offset_with_folds(
+        &self,
+        buffer: &Buffer,
+        folds: &[Range<usize>],
+        offset: usize,
+        horiz: Option<&ColPosition>,
+        count: usize,
+        mode: Mode,
+    ) -> (usize, Option<ColPosition>) {
+        let count = count.min(MAX_MOTION_COUNT);
+        let is_hidden = |line: usize| {
+            folds
+                .iter()
+                .any(|fold| fold.start < line && line < fold.end)
+        };
+        match self {
+            Movement::Up => {
+                let mut line = buffer.line_of_offset(offset);
+                for _ in 0..count {
+                    match (0..line).rev().find(|line| !is_hidden(*line)) {
+                        Some(prev) => line = prev,
+                        None => break,
+                    }
+                }
+                move_to_line(buffer, &DisplayColumns, offset, line, horiz, mode)
+            }
+            Movement::Down => {
+                let mut line = buffer.line_of_offset(offset);
+                let last_line = buffer.motion_last_line(mode);
+                for _ in 0..count {
+                    match (line + 1..=last_line).find(|line| !is_hidden(*line)) {
+                        Some(next) => line = next,
+                        None => break,
+                    }
+                }
+                move_to_line(buffer, &DisplayColumns, offset, line, horiz, mode)
+            }
+            _ => self.move_offset(buffer, offset, horiz, count, mode),
+        }
+    }
+
+    /// Move `offset`, with `ColPosition::Col` as a display column, see
+    /// [`Movement::update_region`].
+    pub fn move_offset(
+        &self,
+        buffer: &Buffer,
+        offset: usize,
+        horiz: Option<&ColPosition>,
+        count: usize,
+        mode: Mode,
+    ) -> (usize, Option<ColPosition>) {
+        self.move_offset_with_columns(
+            buffer,
+            &DisplayColumns,
+            offset,
+            horiz,
+            count,
+            mode,
+        )
+    }
+
+    /// Same as [`Movement::move_offset`], but `ColPosition::Col` holds the
+    /// horizontal position measured by `columns`.
+    pub fn move_offset_with_columns(
+        &self,
+        buffer: &Buffer,
+        columns: &dyn LineColumns,
+        offset: usize,
+        horiz: Option<&ColPosition>,
+        count: usize,
+        mode: Mode,
+    ) -> (usize, Option<ColPosition>) {
+        let count = count.min(MAX_MOTION_COUNT);
+        match self {
+            Movement::Left => {
+                let new_offset = buffer.move_left(offset, mode, count);
+                (new_offset, None)
+            }
+            Movement::Right => {
+                let new_offset = buffer.move_right(offset, mode, count);
+                (new_offset, None)
+            }
+            Movement::VisualLeft => {
+                let new_offset = buffer.move_visual(offset, mode, count, false);
+                (new_offset, None)
+            }
+            Movement::VisualRight => {
+                let new_offset = buffer.move_visual(offset, mode, count, true);
+                (new_offset, None)
+            }
+            Movement::Up => {
+                let line = buffer.line_of_offset(offset);
+                if line == 0 {
+                    return (offset, horiz.cloned());
+                }
+                let line = line.saturating_sub(count);
+                move_to_line(buffer, columns, offset, line, horiz, mode)
+            }
+            Movement::Down => {
+                let line = buffer.line_of_offset(offset);
+                let line =
+                    (line + count).min(buffer.motion_last_line(mode).max(line));
+                move_to_line(buffer, columns, offset, line, horiz, mode)
+            }
+            Movement::PageUp(_)
+            | Movement::PageDown(_)
+            | Movement::HalfPageUp(_)
+            | Movement::HalfPageDown(_) => match self.page_step() {
+                Some((movement, lines)) => movement.move_offset_with_columns(
+                    buffer,
+                    columns,
+                    offset,
+                    horiz,
+                    count.saturating_mul(lines),
+                    mode,
+                ),
+                None => (offset, horiz.cloned()),
+            },
+            // Without a layout every line is a single display row
+            Movement::DisplayUp => Movement::Up.move_offset_with_columns(
+                buffer, columns, offset, horiz, count, mode,
+            ),
+            Movement::DisplayDown => Movement::Down.move_offset_with_columns(
+                buffer, columns, offset, horiz, count, mode,
+            ),
+            Movement::DisplayStartOfLine => Movement::StartOfLine
+                .move_offset_with_columns(
+                    buffer, columns, offset, horiz, count, mode,
+                ),
+            Movement::DisplayEndOfLine => Movement::EndOfLine
+                .move_offset_with_columns(
+                    buffer, columns, offset, horiz, count, mode,
+                ),
+            Movement::DocumentStart => (0, Some(ColPosition::Start)),
+            Movement::DocumentEnd => {
+                let last_offset =
+                    buffer.offset_line_end(buffer.len(), mode != Mode::Normal);
+                (last_offset, Some(ColPosition::End))
+            }
+            Movement::FirstNonBlank => {
+                let line = buffer.line_of_offset(offset);
+                let non_blank_offset = buffer.first_non_blank_clamped(line, mode);
+                let start_line_offset = buffer.offset_of_line(line);
+                if offset > non_blank_offset || start_line_offset == offset {
+                    (non_blank_offset, Some(ColPosition::FirstNonBlank))
+                } else {
+                    (start_line_offset, Some(ColPosition::Start))
+                }
+            }
+            Movement::SmartHome => {
+                // The same toggle as FirstNonBlank, except that only the
+                // first non-blank character itself goes to the line start
+                let line = buffer.line_of_offset(offset);
+                let non_blank_offset = buffer.first_non_blank_clamped(line, mode);
+                let from = if offset == non_blank_offset {
+                    offset
+                } else {
+                    buffer.offset_of_line(line)
+                };
+                Movement::FirstNonBlank.move_offset(buffer, from, horiz, count, mode)
+            }
+            Movement::LastNonBlank => {
+                let line = buffer.line_of_offset(offset);
+                let line = (line + count.saturating_sub(1))
+                    .min(buffer.motion_last_line(mode).max(line));
+                let line_start = buffer.offset_of_line(line);
+                let line_end = buffer.line_end_offset(line, true);
+                let content = buffer.slice_to_cow(line_start..line_end);
+                let trimmed_end = line_start + content.trim_end().len();
+                let new_offset =
+                    buffer.prev_grapheme_offset(trimmed_end, 1, line_start);
+                let col = columns.horiz_of_offset(buffer, new_offset);
+                (new_offset, Some(ColPosition::Col(col)))
+            }
+            Movement::ColumnGoto(col) => {
+                let line = buffer.line_of_offset(offset);
+                let new_offset = buffer
+                    .offset_of_line_display_col(line, *col)
+                    .min(buffer.line_end_offset(line, mode != Mode::Normal));
+                let col = columns.horiz_of_offset(buffer, new_offset);
+                (new_offset, Some(ColPosition::Col(col)))
+            }
+            Movement::StartOfLine => {
+                let line = buffer.line_of_offset(offset);
+                let new_offset = buffer.offset_of_line(line);
+                (new_offset, Some(ColPosition::Start))
+            }
+            Movement::EndOfLine => {
+                let new_offset =
+                    buffer.offset_line_end(offset, mode != Mode::Normal);
+                (new_offset, Some(ColPosition::End))
+            }
+            Movement::Line(position) => {
+                let line = match position {
+                    LinePosition::Line(line) => {
+                        (*line).min(buffer.motion_last_line(mode))
+                    }
+                    LinePosition::First => 0,
+                    LinePosition::Last => buffer.motion_last_line(mode),
+                    LinePosition::Relative(delta) => buffer.relative_line(
+                        buffer.line_of_offset(offset),
+                        *delta,
+                        mode,
+                    ),
+                };
+                move_to_line(buffer, columns, offset, line, horiz, mode)
+            }
+            Movement::GotoLine(line) => {
+                let line = line.saturating_sub(1).min(buffer.motion_last_line(mode));
+                let new_offset = buffer.first_non_blank_clamped(line, mode);
+                (new_offset, Some(ColPosition::FirstNonBlank))
+            }
+            Movement::NextChange(hunks) | Movement::PrevChange(hunks) => {
+                if hunks.is_empty() {
+                    return (offset, horiz.cloned());
+                }
+                let forward = matches!(self, Movement::NextChange(_));
+                let mut line = buffer.line_of_offset(offset);
+                // Every hunk is visited once within `hunks.len()` jumps
+                for _ in 0..count.min(hunks.len()) {
+                    let hunk = if forward {
+                        hunks
+                            .iter()
+                            .find(|hunk| hunk.start > line)
+                            .unwrap_or(&hunks[0])
+                    } else {
+                        hunks
+                            .iter()
+                            .rev()
+                            .find(|hunk| hunk.start < line)
+                            .unwrap_or(&hunks[hunks.len() - 1])
+                    };
+                    line = hunk.start;
+                }
+                let line = line.min(buffer.motion_last_line(mode));
+                let new_offset = buffer.first_non_blank_clamped(line, mode);
+                (new_offset, Some(ColPosition::FirstNonBlank))
+            }
+            Movement::NextFoldMarker(markers)
+            | Movement::PrevFoldMarker(markers) => {
+                let is_marker = |line: &usize| {
+                    let content = buffer.line_content(*line);
+                    let content = content.trim_start();
+                    markers.iter().any(|marker| {
+                        !marker.is_empty() && content.starts_with(marker)
+                    })
+                };
+                let mut line = buffer.line_of_offset(offset);
+                for _ in 0..count {
+                    let next = if matches!(self, Movement::NextFoldMarker(_)) {
+                        (line + 1..=buffer.motion_last_line(mode)).find(is_marker)
+                    } else {
+                        (0..line).rev().find(is_marker)
+                    };
+                    match next {
+                        Some(next) => line = next,
+                        None => break,
+                    }
+                }
+                if line == buffer.line_of_offset(offset) {
+                    return (offset, horiz.cloned());
+                }
+                let new_offset = buffer.first_non_blank_clamped(line, mode);
+                (new_offset, Some(ColPosition::FirstNonBlank))
+            }
+            Movement::Offset(offset) => {
+                let new_offset = buffer.prev_grapheme_offset(*offset + 1, 1, 0);
+                (new_offset, None)
+            }
+            Movement::WordEndForward => {
+                let new_offset = buffer.move_n_wordends_forward(
+                    offset,
+                    count,
+                    mode == Mode::Insert,
+                );
+                let col = columns.horiz_of_offset(buffer, new_offset);
+                (new_offset, Some(ColPosition::Col(col)))
+            }
+            Movement::WordForward => {
+                let new_offset = buffer.move_n_words_forward(offset, count);
+                (new_offset, None)
+            }
+            Movement::WordBackward => {
+                let new_offset = buffer.move_n_words_backward(offset, count);
+                (new_offset, None)
+            }
+            Movement::PathSegmentForward(separators) => {
+                let new_offset =
+                    buffer.move_n_path_segments_forward(offset, count, separators);
+                (new_offset, None)
+            }
+            Movement::PathSegmentBackward(separators) => {
+                let new_offset =
+                    buffer.move_n_path_segments_backward(offset, count, separators);
+                (new_offset, None)
+            }
+            Movement::IntraWordForward => {
+                let new_offset = buffer.move_n_subwords_forward(offset, count);
+                (new_offset, None)
+            }
+            Movement::IntraWordBackward => {
+                let new_offset = buffer.move_n_subwords_backward(offset, count);
+                (new_offset, None)
+            }
+            Movement::SubwordForward => {
+                let new_offset = buffer.subword_forward(offset, count);
+                let col = columns.horiz_of_offset(buffer, new_offset);
+                (new_offset, Some(ColPosition::Col(col)))
+            }
+            Movement::SubwordBackward => {
+                let new_offset = buffer.subword_backward(offset, count);
+                let col = columns.horiz_of_offset(buffer, new_offset);
+                (new_offset, Some(ColPosition::Col(col)))
+            }
+            Movement::FindChar { ch, forward, till } => {
+                let line = buffer.line_of_offset(offset);
+                let line_start = buffer.offset_of_line(line);
+                let line_end = buffer.line_end_offset(line, true);
+                let found = if *forward {
+                    let start = buffer.next_grapheme_offset(offset, 1, line_end);
+                    buffer
+                        .char_indices_iter(start..line_end)
+                        .filter(|(_, c)| c == ch)
+                        .nth(count.saturating_sub(1))
+                        .map(|(i, _)| start + i)
+                } else {
+                    buffer
+                        .char_indices_iter(line_start..offset)
+                        .filter(|(_, c)| c == ch)
+                        .collect::<Vec<_>>()
+                        .into_iter()
+                        .rev()
+                        .nth(count.saturating_sub(1))
+                        .map(|(i, _)| line_start + i)
+                };
+                let new_offset = match found {
+                    Some(found) if *till && *forward => {
+                        buffer.prev_grapheme_offset(found, 1, line_start)
+                    }
+                    Some(found) if *till => {
+                        buffer.next_grapheme_offset(found, 1, line_end)
+                    }
+                    Some(found) => found,
+                    None => return (offset, horiz.cloned()),
+                };
+                let col = columns.horiz_of_offset(buffer, new_offset);
+                (new_offset, Some(ColPosition::Col(col)))
+            }
+            Movement::ToNthChar(ch, n) => {
+                let start = buffer.next_grapheme_offset(offset, 1, buffer.len());
+                let found = n.checked_sub(1).and_then(|n| {
+                    buffer
+                        .char_indices_iter(start..buffer.len())
+                        .filter(|(_, c)| c == ch)
+                        .nth(n)
+                        .map(|(i, _)| start + i)
+                });
+                match found {
+                    Some(new_offset) => {
+                        let col = columns.horiz_of_offset(buffer, new_offset);
+                        (new_offset, Some(ColPosition::Col(col)))
+                    }
+                    None => (offset, horiz.cloned()),
+                }
+            }
+            Movement::NextUnmatched(c) => {
+                let new_offset = WordCursor::new(buffer.text(), offset)
+                    .next_unmatched(*c)
+                    .map_or(offset, |new| new - 1);
+                (new_offset, None)
+            }
+            Movement::PreviousUnmatched(c) => {
+                let new_offset = WordCursor::new(buffer.text(), offset)
+                    .previous_unmatched(*c)
+                    .unwrap_or(offset);
+                (new_offset, None)
+            }
+            Movement::MatchPairs => {
+                let new_offset = WordCursor::new(buffer.text(), offset)
+                    .match_pairs()
+                    .unwrap_or(offset);
+                (new_offset, None)
+            }
+            Movement::MatchingBracket(look_behind) => {
+                match buffer.find_bracket_on_line(offset, *look_behind).and_then(
+                    |bracket| WordCursor::new(buffer.text(), bracket).match_pairs(),
+                ) {
+                    Some(new_offset) => {
+                        let col = columns.horiz_of_offset(buffer, new_offset);
+                        (new_offset, Some(ColPosition::Col(col)))
+                    }
+                    None => (offset, horiz.cloned()),
+                }
+            }
+            Movement::EnclosingBracketOpen => {
+                let new_offset = WordCursor::new(buffer.text(), offset)
+                    .previous_enclosing_bracket()
+                    .unwrap_or(offset);
+                (new_offset, None)
+            }
+            Movement::EnclosingBracketClose => {
+                // Outside of insert mode the cursor is on a character, which
+                // is not inside the brackets if it's a bracket itself.
+                let start = if mode == Mode::Insert {
+                    offset
+                } else {
+                    buffer.next_grapheme_offset(offset, 1, buffer.len())
+                };
+                let new_offset = WordCursor::new(buffer.text(), start)
+                    .next_enclosing_bracket()
+                    .unwrap_or(offset);
+                (new_offset, None)
+            }
+            Movement::ScopeStart => {
+                let new_offset = WordCursor::new(buffer.text(), offset)
+                    .previous_unmatched('{')
+                    .map_or(offset, |open| open + 1);
+                (new_offset, None)
+            }
+            Movement::ScopeEnd => {
+                // Like `EnclosingBracketClose`, a `}` under the cursor doesn't
+                // count, and outside of insert mode the cursor ends up on the
+                // last character before the `}` rather than on it.
+                let start = if mode == Mode::Insert {
+                    offset
+                } else {
+                    buffer.next_grapheme_offset(offset, 1, buffer.len())
+                };
+                let new_offset = WordCursor::new(buffer.text(), start)
+                    .next_unmatched('}')
+                    .map_or(offset, |close| {
+                        let close = close - 1;
+                        if mode == Mode::Insert {
+                            close
+                        } else {
+                            buffer.prev_grapheme_offset(close, 1, 0)
+                        }
+                    });
+                (new_offset, None)
+            }
+        }
+    }
+}
+
+/// Move to `line`, keeping the column described by `horiz`, or the current
+/// column of `offset` if there is none yet.
+fn move_to_line(
+    buffer: &Buffer,
+    columns: &dyn LineColumns,
+    offset: usize,
+    line: usize,
+    horiz: Option<&ColPosition>,
+    mode: Mode,
+) -> (usize, Option<ColPosition>) {
+    let horiz = horiz.cloned().unwrap_or_else(|| {
+        ColPosition::Col(columns.horiz_of_offset(buffer, offset))
+    });
+    let caret = mode != Mode::Normal;
+    let col = match horiz {
+        ColPosition::Col(col) => columns
+            .col_of_horiz(buffer, line, col)
+            .min(buffer.line_end_col(line, caret)),
+        _ => buffer.line_horiz_col(line, &horiz, caret),
+    };
+    (buffer.offset_of_line_col(line, col), Some(horiz))
+}
+
+fn display_rows(
+    buffer: &Buffer,
+    layout: &dyn LineLayout,
+    line: usize,
+) -> Vec<usize> {
+    let rows = layout.row_starts(buffer, line);
+    if rows.is_empty() {
+        vec![0]
+    } else {
+        rows
+    }
+}
+
+fn move_display_rows(
+    buffer: &Buffer,
+    layout: &dyn LineLayout,
+    offset: usize,
+    horiz: Option<&ColPosition>,
+    count: usize,
+    down: bool,
+    mode: Mode,
+) -> (usize, Option<ColPosition>) {
+    let (mut line, col) = buffer.offset_to_line_col(offset);
+    let mut rows = display_rows(buffer, layout, line);
+    let mut row = rows
+        .partition_point(|start| *start <= col)
+        .saturating_sub(1);
+    let horiz = horiz
+        .cloned()
+        .unwrap_or_else(|| ColPosition::Col((col - rows[row]) as f64));
+
+    for _ in 0..count {
+        if down && row + 1 < rows.len() {
+            row += 1;
+        } else if down && line < buffer.last_line() {
+            line += 1;
+            rows = display_rows(buffer, layout, line);
+            row = 0;
+        } else if !down && row > 0 {
+            row -= 1;
+        } else if !down && line > 0 {
+            line -= 1;
+            rows = display_rows(buffer, layout, line);
+            row = rows.len() - 1;
+        } else {
+            break;
+        }
+    }
+
+    let row_start = rows[row];
+    // The cursor can't be after the last character of a row that is
+    // wrapped, since that is the start of the next row.
+    let row_end = match rows.get(row + 1) {
+        Some(next) => next.saturating_sub(1).max(row_start),
+        None => buffer.line_end_col(line, mode != Mode::Normal),
+    };
+    let col = match horiz {
+        ColPosition::Col(col) => row_start + col as usize,
+        ColPosition::End => row_end,
+        ColPosition::Start => row_start,
+        ColPosition::FirstNonBlank if row == 0 => {
+            buffer.first_non_blank_character_on_line(line)
+                - buffer.offset_of_line(line)
+        }
+        ColPosition::FirstNonBlank => row_start,
+    };
+    let new_offset = buffer.offset_of_line_col(line, col.min(row_end));
+    (new_offset, Some(horiz))
+}
+
+/// Move to the start or the end of the display row `offset` is on. When
+/// it's already there, move on to the start or the end of the line instead,
+/// so that pressing `Home` or `End` twice gets there.
+fn move_display_row_edge(
+    buffer: &Buffer,
+    layout: &dyn LineLayout,
+    offset: usize,
+    end: bool,
+    mode: Mode,
+) -> (usize, Option<ColPosition>) {
+    let (line, col) = buffer.offset_to_line_col(offset);
+    let rows = display_rows(buffer, layout, line);
+    let row = rows
+        .partition_point(|start| *start <= col)
+        .saturating_sub(1);
+    let line_end = buffer.line_end_col(line, mode != Mode::Normal);
+    let (col, horiz) = if end {
+        // Like for display rows movements, the end of a wrapped row is its
+        // last character, since the column after it starts the next row
+        let row_end = match rows.get(row + 1) {
+            Some(next) => next.saturating_sub(1).max(rows[row]),
+            None => line_end,
+        };
+        if col == row_end {
+            (line_end, ColPosition::End)
+        } else {
+            (row_end, ColPosition::End)
+        }
+    } else if col == rows[row] {
+        (0, ColPosition::Start)
+    } else {
+        (rows[row], ColPosition::Start)
+    };
+    (buffer.offset_of_line_col(line, col), Some(horiz))
 }
 
 #[cfg(test)]
 mod test {
-    use crate::movement::Movement;
+    use std::ops::Range;
+
+    use crate::{
+        buffer::Buffer,
+        cursor::ColPosition,
+        layout::{LineColumns, LineLayout},
+        mode::Mode,
+        movement::{LinePosition, Movement, MAX_MOTION_COUNT},
+        selection::{SelRegion, Selection},
+    };
 
     #[test]
     fn test_wrapping() {
@@ -134,4 +1242,1497 @@ fn test_non_wrapping() {
         assert_eq!(0, Movement::Up.update_index(0, 5, 2, false));
         assert_eq!(2, Movement::Down.update_index(0, 5, 2, false));
     }
+
+    #[test]
+    fn test_line_last_skips_trailing_newline() {
+        let buffer = Buffer::new("abc\ndef\n");
+        let region = Movement::Line(LinePosition::Last).update_region(
+            &SelRegion::caret(1),
+            &buffer,
+            1,
+            false,
+            Mode::Normal,
+        );
+        assert_eq!(5, region.end);
+        assert_eq!(1, buffer.line_of_offset(region.end));
+
+        let buffer = Buffer::new("abc\ndef");
+        let region = Movement::Line(LinePosition::Last).update_region(
+            &SelRegion::caret(1),
+            &buffer,
+            1,
+            false,
+            Mode::Normal,
+        );
+        assert_eq!(5, region.end);
+    }
+
+    #[test]
+    fn test_huge_count_stops_at_buffer_end() {
+        let buffer = Buffer::new("one two\nthree four\n");
+        let region = Movement::WordForward.update_region(
+            &SelRegion::caret(0),
+            &buffer,
+            usize::MAX,
+            false,
+            Mode::Normal,
+        );
+        assert_eq!(buffer.len(), region.end);
+
+        let region = Movement::Left.update_region(
+            &SelRegion::caret(buffer.len()),
+            &buffer,
+            MAX_MOTION_COUNT * 10,
+            false,
+            Mode::Insert,
+        );
+        assert_eq!(0, region.end);
+    }
+
+    #[test]
+    fn test_vertical_movement_at_document_edge() {
+        let buffer = Buffer::new("abc\ndef\nghi");
+        let up = |offset| {
+            Movement::Up.update_region_with_result(
+                &SelRegion::caret(offset),
+                &buffer,
+                1,
+                false,
+                Mode::Normal,
+            )
+        };
+        let down = |offset| {
+            Movement::Down.update_region_with_result(
+                &SelRegion::caret(offset),
+                &buffer,
+                1,
+                false,
+                Mode::Normal,
+            )
+        };
+
+        assert!(!up(1).moved);
+        assert_eq!(1, up(1).region.end);
+        assert!(up(5).moved);
+        assert_eq!(1, up(5).region.end);
+
+        assert!(!down(9).moved);
+        assert_eq!(9, down(9).region.end);
+        assert!(down(5).moved);
+        assert_eq!(9, down(5).region.end);
+    }
+
+    #[test]
+    fn test_matching_bracket() {
+        let buffer = Buffer::new("x (ab) c\na) b (c)\n(ab) x\nno brackets");
+        //                      ->012345678 901234567 8901234 56789012345<-
+        let movement = |look_behind, offset| {
+            Movement::MatchingBracket(look_behind)
+                .update_region(
+                    &SelRegion::caret(offset),
+                    &buffer,
+                    1,
+                    false,
+                    Mode::Normal,
+                )
+                .end
+        };
+
+        // Before a `(`
+        assert_eq!(5, movement(false, 0));
+        // On a bracket
+        assert_eq!(2, movement(false, 5));
+        // Between a `)` and a later `(`
+        assert_eq!(16, movement(false, 12));
+        // Only brackets before the cursor
+        assert_eq!(23, movement(false, 23));
+        assert_eq!(18, movement(true, 23));
+        // No brackets at all
+        assert_eq!(30, movement(false, 30));
+        assert_eq!(30, movement(true, 30));
+    }
+
+    #[test]
+    fn test_matching_bracket_across_lines() {
+        let buffer = Buffer::new("fn a() {\n    if b { [c] }\n}\n{ (");
+        //                      ->012345678 90123456789012345 67 890<-
+        let movement = |offset| {
+            Movement::MatchingBracket(false).move_offset(
+                &buffer,
+                offset,
+                Some(&ColPosition::End),
+                1,
+                Mode::Normal,
+            )
+        };
+
+        // Past the nested `{ }` to the `}` on the last line
+        assert_eq!((26, Some(ColPosition::Col(0.0))), movement(6));
+        assert_eq!((7, Some(ColPosition::Col(7.0))), movement(26));
+        assert_eq!((24, Some(ColPosition::Col(15.0))), movement(17));
+        // Unmatched brackets leave the cursor and its column alone
+        assert_eq!((28, Some(ColPosition::End)), movement(28));
+        assert_eq!((30, Some(ColPosition::End)), movement(30));
+    }
+
+    #[test]
+    fn test_enclosing_bracket() {
+        let buffer = Buffer::new("{ a ( b [ c ] d ) e }");
+        //                      ->012345678901234567890<-
+        let movement = |movement: Movement, offset| {
+            movement
+                .update_region(
+                    &SelRegion::caret(offset),
+                    &buffer,
+                    1,
+                    false,
+                    Mode::Normal,
+                )
+                .end
+        };
+
+        assert_eq!(8, movement(Movement::EnclosingBracketOpen, 10));
+        assert_eq!(4, movement(Movement::EnclosingBracketOpen, 8));
+        assert_eq!(0, movement(Movement::EnclosingBracketOpen, 4));
+        assert_eq!(0, movement(Movement::EnclosingBracketOpen, 0));
+        assert_eq!(4, movement(Movement::EnclosingBracketOpen, 14));
+
+        assert_eq!(12, movement(Movement::EnclosingBracketClose, 10));
+        assert_eq!(16, movement(Movement::EnclosingBracketClose, 12));
+        assert_eq!(20, movement(Movement::EnclosingBracketClose, 16));
+        assert_eq!(20, movement(Movement::EnclosingBracketClose, 20));
+        assert_eq!(16, movement(Movement::EnclosingBracketClose, 6));
+
+        let buffer = Buffer::new("a (b) c");
+        let region = Movement::EnclosingBracketOpen.update_region(
+            &SelRegion::caret(6),
+            &buffer,
+            1,
+            false,
+            Mode::Normal,
+        );
+        assert_eq!(6, region.end);
+    }
+
+    #[test]
+    fn test_scope() {
+        let buffer =
+            Buffer::new("fn a() {\n    if b {\n        c();\n    }\n    d();\n}");
+        let movement = |movement: Movement, offset, mode| {
+            movement
+                .update_region(&SelRegion::caret(offset), &buffer, 1, false, mode)
+                .end
+        };
+
+        // From `c` in the inner block
+        assert_eq!(19, movement(Movement::ScopeStart, 28, Mode::Insert));
+        assert_eq!(37, movement(Movement::ScopeEnd, 28, Mode::Insert));
+        assert_eq!(19, movement(Movement::ScopeStart, 19, Mode::Insert));
+        assert_eq!(36, movement(Movement::ScopeEnd, 28, Mode::Normal));
+        assert_eq!(36, movement(Movement::ScopeEnd, 36, Mode::Normal));
+
+        // From `d` in the outer block
+        assert_eq!(8, movement(Movement::ScopeStart, 43, Mode::Insert));
+        assert_eq!(48, movement(Movement::ScopeEnd, 43, Mode::Insert));
+
+        // Outside of any braces
+        assert_eq!(2, movement(Movement::ScopeStart, 2, Mode::Insert));
+        assert_eq!(2, movement(Movement::ScopeEnd, 2, Mode::Insert));
+    }
+
+    #[test]
+    fn test_intra_word() {
+        let buffer = Buffer::new("parseHTTPRequest next_word");
+        //                      ->01234567890123456789012345<-
+        let movement = |movement: Movement, offset, count| {
+            movement
+                .update_region(
+                    &SelRegion::caret(offset),
+                    &buffer,
+                    count,
+                    false,
+                    Mode::Insert,
+                )
+                .end
+        };
+
+        let mut offset = 0;
+        for expected in [5, 9, 16, 16] {
+            offset = movement(Movement::IntraWordForward, offset, 1);
+            assert_eq!(expected, offset);
+        }
+        for expected in [9, 5, 0, 0] {
+            offset = movement(Movement::IntraWordBackward, offset, 1);
+            assert_eq!(expected, offset);
+        }
+
+        assert_eq!(16, movement(Movement::IntraWordForward, 2, 10));
+        assert_eq!(22, movement(Movement::IntraWordForward, 17, 1));
+        assert_eq!(26, movement(Movement::IntraWordForward, 22, 1));
+        assert_eq!(17, movement(Movement::IntraWordBackward, 22, 1));
+        assert_eq!(17, movement(Movement::IntraWordBackward, 17, 1));
+    }
+
+    #[test]
+    fn test_path_segment() {
+        let buffer = Buffer::new("/usr/local/bin/rustc");
+        //                      ->01234567890123456789<-
+        let movement = |movement: Movement, offset| {
+            movement
+                .update_region(
+                    &SelRegion::caret(offset),
+                    &buffer,
+                    1,
+                    false,
+                    Mode::Insert,
+                )
+                .end
+        };
+
+        let forward = Movement::PathSegmentForward(Vec::new());
+        let mut offset = 0;
+        for expected in [1, 5, 11, 15, 20, 20] {
+            offset = movement(forward.clone(), offset);
+            assert_eq!(expected, offset);
+        }
+
+        let backward = Movement::PathSegmentBackward(Vec::new());
+        for expected in [15, 11, 5, 1, 0, 0] {
+            offset = movement(backward.clone(), offset);
+            assert_eq!(expected, offset);
+        }
+
+        let buffer = Buffer::new("lib/libfoo-1.so");
+        //                      ->012345678901234<-
+        let forward = Movement::PathSegmentForward(vec!['.', '-']);
+        let region = forward.update_region(
+            &SelRegion::caret(4),
+            &buffer,
+            2,
+            false,
+            Mode::Insert,
+        );
+        assert_eq!(13, region.end);
+    }
+
+    #[test]
+    fn test_goto_line() {
+        let buffer = Buffer::new("a\n  b\n\tc\nd");
+        //                      ->01 2345 678 9<-
+        let goto = |line: usize| {
+            Movement::GotoLine(line).move_offset(
+                &buffer,
+                9,
+                Some(&ColPosition::End),
+                1,
+                Mode::Normal,
+            )
+        };
+
+        assert_eq!((0, Some(ColPosition::FirstNonBlank)), goto(1));
+        assert_eq!((4, Some(ColPosition::FirstNonBlank)), goto(2));
+        assert_eq!((7, Some(ColPosition::FirstNonBlank)), goto(3));
+        assert_eq!((9, Some(ColPosition::FirstNonBlank)), goto(42));
+        assert_eq!((0, Some(ColPosition::FirstNonBlank)), goto(0));
+
+        // Unlike GotoLine, Line is zero-based and keeps the column
+        let line = |line: usize| {
+            Movement::Line(LinePosition::Line(line)).move_offset(
+                &buffer,
+                9,
+                Some(&ColPosition::End),
+                1,
+                Mode::Normal,
+            )
+        };
+        assert_eq!((0, Some(ColPosition::End)), line(0));
+        assert_eq!((4, Some(ColPosition::End)), line(1));
+
+        // A line of only whitespace is gone to on its last column, which is
+        // before the newline outside Insert mode
+        let buffer = Buffer::new("a\n   \nb");
+        let goto = |mode| {
+            Movement::GotoLine(2)
+                .move_offset(&buffer, 0, None, 1, mode)
+                .0
+        };
+        assert_eq!(4, goto(Mode::Normal));
+        assert_eq!(5, goto(Mode::Insert));
+    }
+
+    #[test]
+    fn test_word_backward_across_line_boundary() {
+        let buffer = Buffer::new("foo bar\nbaz\n\nqux");
+        //                      ->0123456 7890 1 234<-
+        let word_backward = |offset, mode| {
+            Movement::WordBackward
+                .update_region(&SelRegion::caret(offset), &buffer, 1, false, mode)
+                .end
+        };
+
+        for mode in [Mode::Insert, Mode::Normal] {
+            assert_eq!(4, word_backward(8, mode));
+            assert_eq!(8, word_backward(9, mode));
+            assert_eq!(12, word_backward(13, mode));
+            assert_eq!(8, word_backward(12, mode));
+        }
+        // Only Insert mode can rest at the end of a line, and going back from
+        // there lands on the start of the last word, not before it.
+        assert_eq!(4, word_backward(7, Mode::Insert));
+        assert_eq!(8, word_backward(11, Mode::Insert));
+    }
+
+    #[test]
+    fn test_scrolloff_target() {
+        let text: Vec<String> = (0..20).map(|i| i.to_string()).collect();
+        let buffer = Buffer::new(&text.join("\n"));
+        let target = |line: usize, viewport: Range<usize>| {
+            let region = SelRegion::caret(buffer.offset_of_line(line));
+            Movement::scrolloff_target(&region, &buffer, viewport, 3)
+        };
+
+        assert_eq!(None, target(5, 0..10));
+        assert_eq!(None, target(6, 0..10));
+        assert_eq!(Some(1), target(7, 0..10));
+        assert_eq!(Some(3), target(9, 0..10));
+
+        assert_eq!(None, target(8, 5..15));
+        assert_eq!(Some(4), target(7, 5..15));
+        assert_eq!(None, target(1, 0..10));
+
+        // The end of the document is already in view
+        assert_eq!(None, target(18, 10..20));
+        assert_eq!(Some(10), target(18, 9..19));
+    }
+
+    #[test]
+    fn test_last_non_blank() {
+        let buffer = Buffer::new("  foo  \n   \nbar");
+        //                      ->0123456 7890 123<-
+        let last_non_blank = |offset, count| {
+            Movement::LastNonBlank.move_offset(
+                &buffer,
+                offset,
+                None,
+                count,
+                Mode::Normal,
+            )
+        };
+
+        assert_eq!((4, Some(ColPosition::Col(4.0))), last_non_blank(0, 1));
+        assert_eq!((4, Some(ColPosition::Col(4.0))), last_non_blank(6, 1));
+        // A line of only whitespace lands at its start
+        assert_eq!((8, Some(ColPosition::Col(0.0))), last_non_blank(9, 1));
+        assert_eq!((8, Some(ColPosition::Col(0.0))), last_non_blank(0, 2));
+        assert_eq!((14, Some(ColPosition::Col(2.0))), last_non_blank(0, 3));
+
+        // A count past the end stays off the line after a trailing newline
+        let buffer = Buffer::new("ab\ncd\n");
+        let (offset, _) =
+            Movement::LastNonBlank.move_offset(&buffer, 0, None, 10, Mode::Normal);
+        assert_eq!(4, offset);
+    }
+
+    /// Wraps every line after the same number of columns.
+    struct Wrap(usize);
+
+    impl LineLayout for Wrap {
+        fn row_starts(&self, buffer: &Buffer, line: usize) -> Vec<usize> {
+            let len = buffer.line_end_col(line, true);
+            (0..len.max(1)).step_by(self.0).collect()
+        }
+    }
+
+    fn char_width(c: char) -> f64 {
+        if c == 'i' {
+            4.0
+        } else {
+            10.0
+        }
+    }
+
+    /// `i` is narrow and every other char is wide, like in a proportional
+    /// font.
+    struct Proportional;
+
+    impl LineColumns for Proportional {
+        fn horiz_of_offset(&self, buffer: &Buffer, offset: usize) -> f64 {
+            let line_start = buffer.offset_of_line(buffer.line_of_offset(offset));
+            buffer
+                .slice_to_cow(line_start..offset)
+                .chars()
+                .map(char_width)
+                .sum()
+        }
+
+        fn col_of_horiz(&self, buffer: &Buffer, line: usize, horiz: f64) -> usize {
+            let content = buffer.line_content(line);
+            let mut x = 0.0;
+            for (i, c) in content.char_indices() {
+                if x + char_width(c) / 2.0 > horiz {
+                    return i;
+                }
+                x += char_width(c);
+            }
+            content.len()
+        }
+    }
+
+    #[test]
+    fn test_move_with_columns() {
+        let buffer = Buffer::new("iiiiiiiix\nabcdefgh");
+        //                      ->0123456789 01234567<-
+        let movement = |movement: Movement, offset, horiz: Option<ColPosition>| {
+            movement.move_offset_with_columns(
+                &buffer,
+                &Proportional,
+                offset,
+                horiz.as_ref(),
+                1,
+                Mode::Normal,
+            )
+        };
+
+        // The column is kept as measured, not as a number of chars
+        let (offset, horiz) = movement(Movement::WordEndForward, 0, None);
+        assert_eq!((8, Some(ColPosition::Col(32.0))), (offset, horiz));
+        let (offset, horiz) = movement(Movement::Down, offset, horiz);
+        assert_eq!((13, Some(ColPosition::Col(32.0))), (offset, horiz));
+        let (offset, horiz) = movement(Movement::Up, offset, horiz);
+        assert_eq!((8, Some(ColPosition::Col(32.0))), (offset, horiz));
+
+        let region = Movement::Down.update_region_with(
+            &SelRegion::caret(8),
+            &buffer,
+            1,
+            true,
+            Mode::Insert,
+            |offset, horiz, count, mode| {
+                Movement::Down.move_offset_with_columns(
+                    &buffer,
+                    &Proportional,
+                    offset,
+                    horiz,
+                    count,
+                    mode,
+                )
+            },
+        );
+        assert_eq!(region, SelRegion::new(8, 13, Some(ColPosition::Col(32.0))));
+    }
+
+    #[test]
+    fn test_vertical_move_over_fold() {
+        // Lines 2 to 4 are folded with line 2 as the header, and so are lines
+        // 7 and 8 with line 7 as the header
+        let buffer = Buffer::new("l0\nl1\nl2\nl3\nl4\nl5\nl6\nl7\nl8\nl9");
+        let folds = [2..5, 7..9];
+        let line = |movement: Movement, line, count| {
+            let (offset, _) = movement.move_offset_with_folds(
+                &buffer,
+                &folds,
+                buffer.offset_of_line(line),
+                None,
+                count,
+                Mode::Normal,
+            );
+            buffer.line_of_offset(offset)
+        };
+
+        // Into the fold, onto its header
+        assert_eq!(2, line(Movement::Down, 1, 1));
+        assert_eq!(2, line(Movement::Down, 0, 2));
+        // Over the folded lines
+        assert_eq!(5, line(Movement::Down, 2, 1));
+        assert_eq!(5, line(Movement::Down, 1, 2));
+        assert_eq!(2, line(Movement::Up, 5, 1));
+        assert_eq!(0, line(Movement::Up, 5, 3));
+        // From a line that has been folded away
+        assert_eq!(5, line(Movement::Down, 3, 1));
+        assert_eq!(2, line(Movement::Up, 4, 1));
+        // From one fold to the next, and past the last one
+        assert_eq!(7, line(Movement::Down, 2, 3));
+        assert_eq!(9, line(Movement::Down, 7, 1));
+        assert_eq!(9, line(Movement::Down, 5, 10));
+        assert_eq!(2, line(Movement::Up, 9, 4));
+        assert_eq!(0, line(Movement::Up, 0, 1));
+    }
+
+    #[test]
+    fn test_display_down_and_up() {
+        // The first line is displayed as `abcd`, `efgh` and `ij`
+        let buffer = Buffer::new("abcdefghij\nxy\n");
+        let layout = Wrap(4);
+        let movement = |movement: Movement, offset, horiz: Option<ColPosition>| {
+            movement.move_offset_with_layout(
+                &buffer,
+                &layout,
+                offset,
+                horiz.as_ref(),
+                1,
+                Mode::Normal,
+            )
+        };
+
+        // `j` moves by line, `gj` by display row
+        assert_eq!(12, movement(Movement::Down, 1, None).0);
+        let (offset, horiz) = movement(Movement::DisplayDown, 1, None);
+        assert_eq!((5, Some(ColPosition::Col(1.0))), (offset, horiz));
+        let (offset, horiz) = movement(Movement::DisplayDown, offset, horiz);
+        assert_eq!(9, offset);
+        // From the last row of a wrapped line to the first row of the next
+        let (offset, horiz) = movement(Movement::DisplayDown, offset, horiz);
+        assert_eq!(12, offset);
+
+        let (offset, horiz) = movement(Movement::DisplayUp, offset, horiz);
+        assert_eq!(9, offset);
+        let (offset, _) = movement(Movement::DisplayUp, offset, horiz);
+        assert_eq!(5, offset);
+
+        // A column past the end of a wrapped row stays on that row
+        let (offset, _) =
+            movement(Movement::DisplayUp, 9, Some(ColPosition::Col(3.0)));
+        assert_eq!(7, offset);
+        assert_eq!(1, movement(Movement::Up, 12, None).0);
+
+        // Without a layout, display rows are lines
+        let (offset, _) =
+            Movement::DisplayDown.move_offset(&buffer, 1, None, 1, Mode::Normal);
+        assert_eq!(12, offset);
+    }
+
+    #[test]
+    fn test_logical_move_over_wrapped_line() {
+        //                        0123 45678901234 5678
+        let buffer = Buffer::new("abc\nabcdefghijk\nabc");
+        let layout = Wrap(4);
+        let movement = |movement: Movement, offset| {
+            movement.move_offset_with_layout(
+                &buffer,
+                &layout,
+                offset,
+                None,
+                1,
+                Mode::Normal,
+            )
+        };
+
+        // Line 1 is shown as three rows, which `j` and `k` go past at once
+        assert_eq!(
+            (6, Some(ColPosition::Col(2.0))),
+            movement(Movement::Down, 2)
+        );
+        assert_eq!(
+            (18, Some(ColPosition::Col(2.0))),
+            movement(Movement::Down, 6)
+        );
+        assert_eq!(
+            (18, Some(ColPosition::Col(9.0))),
+            movement(Movement::Down, 13)
+        );
+        assert_eq!((6, Some(ColPosition::Col(2.0))), movement(Movement::Up, 18));
+        assert_eq!((2, Some(ColPosition::Col(9.0))), movement(Movement::Up, 13));
+    }
+
+    #[test]
+    fn test_display_start_and_end_of_line() {
+        //                        0123456789 012
+        let buffer = Buffer::new("abcdefghi\nxyz");
+        let layout = Wrap(4);
+        let movement = |movement: Movement, offset| {
+            movement
+                .move_offset_with_layout(
+                    &buffer,
+                    &layout,
+                    offset,
+                    None,
+                    1,
+                    Mode::Normal,
+                )
+                .0
+        };
+
+        // The line is shown as the rows `abcd`, `efgh` and `i`
+        assert_eq!(4, movement(Movement::DisplayStartOfLine, 6));
+        assert_eq!(0, movement(Movement::DisplayStartOfLine, 4));
+        assert_eq!(0, movement(Movement::DisplayStartOfLine, 0));
+        assert_eq!(7, movement(Movement::DisplayEndOfLine, 5));
+        assert_eq!(8, movement(Movement::DisplayEndOfLine, 7));
+        assert_eq!(8, movement(Movement::DisplayEndOfLine, 8));
+
+        // A line that isn't wrapped is a single row
+        assert_eq!(10, movement(Movement::DisplayStartOfLine, 12));
+        assert_eq!(12, movement(Movement::DisplayEndOfLine, 11));
+
+        // Without a layout, display rows are lines
+        let (offset, _) = Movement::DisplayEndOfLine.move_offset(
+            &buffer,
+            5,
+            None,
+            1,
+            Mode::Normal,
+        );
+        assert_eq!(8, offset);
+    }
+
+    #[test]
+    fn test_vertical_move_clamped() {
+        let buffer = Buffer::new("abcdef\nab\nabcdefgh");
+        //                      ->0123456 789 01234567<-
+        let down = |offset, horiz: Option<ColPosition>| {
+            Movement::Down.update_region_vertical(
+                &SelRegion::new(offset, offset, horiz),
+                &buffer,
+                1,
+                false,
+                Mode::Insert,
+            )
+        };
+
+        let result = down(5, None);
+        assert_eq!(9, result.region.end);
+        assert!(result.clamped);
+
+        let result = down(result.region.end, result.region.horiz);
+        assert_eq!(15, result.region.end);
+        assert!(!result.clamped);
+
+        let result = down(2, Some(ColPosition::End));
+        assert_eq!(9, result.region.end);
+        assert!(!result.clamped);
+    }
+
+    #[test]
+    fn test_vertical_move_through_short_line() {
+        let long = "x".repeat(40);
+        let buffer = Buffer::new(&format!("{long}\nab\n{long}\nab\n{long}"));
+        let movement = |movement: Movement, region: &SelRegion| {
+            movement.update_region(region, &buffer, 1, false, Mode::Normal)
+        };
+
+        let region = SelRegion::caret(30);
+        let region = movement(Movement::Down, &region);
+        assert_eq!(buffer.offset_to_line_col(region.end), (1, 1));
+        let region = movement(Movement::Down, &region);
+        assert_eq!(buffer.offset_to_line_col(region.end), (2, 30));
+        let region = movement(Movement::Down, &region);
+        let region = movement(Movement::Down, &region);
+        assert_eq!(buffer.offset_to_line_col(region.end), (4, 30));
+        let region = movement(Movement::Up, &region);
+        let region = movement(Movement::Up, &region);
+        assert_eq!(buffer.offset_to_line_col(region.end), (2, 30));
+        assert_eq!(region.horiz, Some(ColPosition::Col(30.0)));
+    }
+
+    #[test]
+    fn test_vertical_move_keeps_end() {
+        let buffer = Buffer::new("abcdef\nab\n\nabcdefgh\nabcd");
+        //                      ->0123456 789 0 123456789 0123<-
+        let ends = |mode| {
+            let mut region = SelRegion::new(0, 0, Some(ColPosition::End));
+            let mut ends = Vec::new();
+            for _ in 0..buffer.last_line() {
+                region =
+                    Movement::Down.update_region(&region, &buffer, 1, false, mode);
+                assert_eq!(Some(ColPosition::End), region.horiz);
+                ends.push(region.end);
+            }
+            ends
+        };
+
+        assert_eq!(ends(Mode::Insert), vec![9, 10, 19, 24]);
+        assert_eq!(ends(Mode::Normal), vec![8, 10, 18, 23]);
+
+        // A column past a short line's end is kept for the lines after it.
+        let mut region = SelRegion::new(5, 5, None);
+        for expected in [9, 10, 16, 24] {
+            region = Movement::Down.update_region(
+                &region,
+                &buffer,
+                1,
+                false,
+                Mode::Insert,
+            );
+            assert_eq!(Some(ColPosition::Col(5.0)), region.horiz);
+            assert_eq!(expected, region.end);
+        }
+    }
+
+    #[test]
+    fn test_vertical_move_by_display_col() {
+        // "中" is a single fullwidth char, three bytes long
+        let buffer = Buffer::new("a中b\nabcde\n");
+        let movement = |movement: Movement, offset, horiz: Option<ColPosition>| {
+            movement.move_offset(&buffer, offset, horiz.as_ref(), 1, Mode::Insert)
+        };
+
+        let (offset, horiz) = movement(Movement::Right, 0, None);
+        assert_eq!((1, None), (offset, horiz));
+        let (offset, horiz) = movement(Movement::Right, offset, horiz);
+        assert_eq!((4, None), (offset, horiz));
+
+        // `b` is in display column 3, above `d` rather than `e`
+        let (offset, horiz) = movement(Movement::Down, offset, horiz);
+        assert_eq!((9, Some(ColPosition::Col(3.0))), (offset, horiz));
+        let (offset, _) = movement(Movement::Up, offset, horiz);
+        assert_eq!(4, offset);
+
+        // Both halves of a wide char are above it
+        assert_eq!(7, movement(Movement::Down, 1, None).0);
+        assert_eq!(1, movement(Movement::Up, 8, None).0);
+        assert_eq!(1, movement(Movement::Up, 7, None).0);
+    }
+
+    #[test]
+    fn test_visual_line() {
+        let buffer = Buffer::new("l0\nl1\nl2\nl3");
+        //                      ->012 345 678 90<-
+        let movement = |movement: Movement, region: SelRegion| {
+            let region =
+                movement.update_region(&region, &buffer, 1, true, Mode::VisualLine);
+            (region.start, region.end)
+        };
+        let region = |(start, end)| SelRegion::new(start, end, None);
+
+        // Starting from a caret on line 1
+        assert_eq!((3, 9), movement(Movement::Down, SelRegion::caret(4)));
+        // The last line has no newline to include
+        assert_eq!((3, 11), movement(Movement::Down, region((3, 9))));
+        assert_eq!((3, 11), movement(Movement::Down, region((3, 11))));
+        assert_eq!((3, 9), movement(Movement::Up, region((3, 11))));
+        assert_eq!((3, 6), movement(Movement::Up, region((3, 9))));
+
+        // Moving above the anchor flips the region around it
+        assert_eq!((6, 0), movement(Movement::Up, region((3, 6))));
+        assert_eq!((6, 0), movement(Movement::Up, region((6, 0))));
+        assert_eq!((3, 6), movement(Movement::Down, region((6, 0))));
+
+        // Anchored on the last line
+        assert_eq!((11, 6), movement(Movement::Up, SelRegion::caret(10)));
+        assert_eq!((11, 3), movement(Movement::Up, region((11, 6))));
+        assert_eq!((9, 11), movement(Movement::Down, region((11, 9))));
+    }
+
+    #[test]
+    fn test_change() {
+        let buffer = Buffer::new("l0\n  l1\nl2\nl3\n    l4\nl5\nl6\nl7\n  l8\nl9");
+        let hunks = vec![1..2, 4..6, 8..8];
+        let line = |movement: Movement, line, count| {
+            let (offset, horiz) = movement.move_offset(
+                &buffer,
+                buffer.offset_of_line(line),
+                None,
+                count,
+                Mode::Normal,
+            );
+            assert_eq!(Some(ColPosition::FirstNonBlank), horiz);
+            assert_eq!(
+                offset,
+                buffer.first_non_blank_character_on_line(
+                    buffer.line_of_offset(offset),
+                )
+            );
+            buffer.line_of_offset(offset)
+        };
+        let next = Movement::NextChange(hunks.clone());
+        let prev = Movement::PrevChange(hunks);
+
+        assert_eq!(1, line(next.clone(), 0, 1));
+        assert_eq!(4, line(next.clone(), 1, 1));
+        assert_eq!(4, line(next.clone(), 2, 1));
+        assert_eq!(8, line(next.clone(), 5, 1));
+        assert_eq!(8, line(next.clone(), 0, 3));
+        // Past the last hunk, around to the first
+        assert_eq!(1, line(next.clone(), 8, 1));
+        assert_eq!(1, line(next.clone(), 9, 1));
+
+        assert_eq!(4, line(prev.clone(), 7, 1));
+        // Inside a hunk, to its own first line
+        assert_eq!(4, line(prev.clone(), 5, 1));
+        assert_eq!(1, line(prev.clone(), 4, 1));
+        assert_eq!(1, line(prev.clone(), 9, 3));
+        // Before the first hunk, around to the last
+        assert_eq!(8, line(prev.clone(), 1, 1));
+        assert_eq!(8, line(prev, 0, 1));
+
+        let (offset, horiz) = Movement::NextChange(Vec::new()).move_offset(
+            &buffer,
+            4,
+            Some(&ColPosition::Col(1.0)),
+            1,
+            Mode::Normal,
+        );
+        assert_eq!((4, Some(ColPosition::Col(1.0))), (offset, horiz));
+
+        // A hunk on a line of only whitespace, and one past the last line
+        //                        01 234 567
+        let buffer = Buffer::new("a\n  \nb\n");
+        let next = |line, mode| {
+            Movement::NextChange(vec![1..2, 5..6])
+                .move_offset(&buffer, buffer.offset_of_line(line), None, 1, mode)
+                .0
+        };
+        assert_eq!(3, next(0, Mode::Normal));
+        assert_eq!(4, next(0, Mode::Insert));
+        assert_eq!(5, next(1, Mode::Normal));
+        assert_eq!(7, next(1, Mode::Insert));
+    }
+
+    #[test]
+    fn test_fold_marker() {
+        let buffer = Buffer::new(
+            "// region: a\nfn a() {}\n// endregion\n\nimpl A {\n    #region b\n    fn b() {}\n    #endregion\n}",
+        );
+        // 0 // region: a
+        // 1 fn a() {}
+        // 2 // endregion
+        // 3
+        // 4 impl A {
+        // 5     #region b
+        // 6     fn b() {}
+        // 7     #endregion
+        // 8 }
+        let markers = vec![
+            "// region".to_string(),
+            "// endregion".to_string(),
+            "#region".to_string(),
+            "#endregion".to_string(),
+        ];
+        let next = Movement::NextFoldMarker(markers.clone());
+        let prev = Movement::PrevFoldMarker(markers);
+        let movement = |movement: &Movement, line, count| {
+            movement.move_offset(
+                &buffer,
+                buffer.offset_of_line(line),
+                Some(&ColPosition::End),
+                count,
+                Mode::Normal,
+            )
+        };
+        let line = |(offset, horiz)| {
+            assert_eq!(Some(ColPosition::FirstNonBlank), horiz);
+            buffer.line_of_offset(offset)
+        };
+
+        assert_eq!(2, line(movement(&next, 0, 1)));
+        assert_eq!(5, line(movement(&next, 2, 1)));
+        assert_eq!(7, line(movement(&next, 0, 4)));
+        // Runs out of markers on the way
+        assert_eq!(7, line(movement(&next, 0, 10)));
+        assert_eq!(
+            (
+                buffer.offset_of_line(5) + 4,
+                Some(ColPosition::FirstNonBlank)
+            ),
+            movement(&next, 4, 1)
+        );
+
+        assert_eq!(5, line(movement(&prev, 7, 1)));
+        assert_eq!(0, line(movement(&prev, 8, 4)));
+
+        // No markers left, so nothing moves
+        let end = buffer.offset_of_line(8);
+        assert_eq!((end, Some(ColPosition::End)), movement(&next, 8, 1));
+        assert_eq!((0, Some(ColPosition::End)), movement(&prev, 0, 1));
+        assert_eq!(
+            (end, Some(ColPosition::End)),
+            movement(&Movement::NextFoldMarker(Vec::new()), 8, 1)
+        );
+    }
+
+    #[test]
+    fn test_to_nth_char() {
+        //                        0123456789 01234
+        let buffer = Buffer::new("a,b,c,d,e\n,f,g,");
+        let to = |n, offset| {
+            Movement::ToNthChar(',', n).move_offset(
+                &buffer,
+                offset,
+                None,
+                1,
+                Mode::Normal,
+            )
+        };
+
+        assert_eq!((5, Some(ColPosition::Col(5.0))), to(3, 0));
+        // The char under the cursor doesn't count
+        assert_eq!((5, Some(ColPosition::Col(5.0))), to(2, 1));
+        // Going on to the next line
+        assert_eq!((10, Some(ColPosition::Col(0.0))), to(5, 0));
+        assert_eq!((14, Some(ColPosition::Col(4.0))), to(4, 5));
+
+        assert_eq!((0, None), to(8, 0));
+        assert_eq!((0, None), to(0, 0));
+    }
+
+    #[test]
+    fn test_find_char() {
+        // The offsets are 0 1 2 4 5 6 8 9 for "a,éb,é c", as "é" is two bytes
+        let buffer = Buffer::new("a,éb,é c\né");
+        let find = |ch, forward, till, offset, count| {
+            let (offset, horiz) = Movement::FindChar { ch, forward, till }
+                .move_offset(
+                    &buffer,
+                    offset,
+                    Some(&ColPosition::End),
+                    count,
+                    Mode::Normal,
+                );
+            let col = match horiz {
+                Some(ColPosition::Col(col)) => Some(col as usize),
+                _ => None,
+            };
+            (offset, col)
+        };
+
+        assert_eq!((2, Some(2)), find('é', true, false, 0, 1));
+        assert_eq!((6, Some(5)), find('é', true, false, 0, 2));
+        assert_eq!((6, Some(5)), find('é', true, false, 2, 1));
+        assert_eq!((5, Some(4)), find('é', true, true, 0, 2));
+        // Already just before it
+        assert_eq!((0, Some(0)), find(',', true, true, 0, 1));
+        assert_eq!((2, Some(2)), find('é', false, false, 9, 2));
+        assert_eq!((8, Some(6)), find('é', false, true, 9, 1));
+        assert_eq!((0, Some(0)), find('a', false, false, 6, 1));
+
+        // Not found on the line, or not often enough
+        assert_eq!((0, None), find('é', true, false, 0, 3));
+        assert_eq!((0, None), find('x', true, false, 0, 1));
+        assert_eq!((9, None), find('é', true, false, 9, 1));
+        assert_eq!((2, None), find('a', false, false, 2, 2));
+    }
+
+    #[test]
+    fn test_page_move() {
+        let buffer = Buffer::new("abcd\nab\nabcd\nabcd\nab\nabcd\nabcd");
+        // 0 abcd
+        // 1 ab
+        // 2 abcd
+        // 3 abcd
+        // 4 ab
+        // 5 abcd
+        // 6 abcd
+        let region = SelRegion::caret(3);
+        let region = Movement::PageDown(1).update_region(
+            &region,
+            &buffer,
+            1,
+            false,
+            Mode::Normal,
+        );
+        assert_eq!((6, Some(ColPosition::Col(3.0))), (region.end, region.horiz));
+        assert_eq!(1, buffer.line_of_offset(region.end));
+
+        // The column is still there after a page
+        let region = Movement::PageDown(2).update_region(
+            &region,
+            &buffer,
+            1,
+            false,
+            Mode::Normal,
+        );
+        assert_eq!(buffer.offset_of_line(3) + 3, region.end);
+
+        let region = Movement::PageDown(2).update_region(
+            &region,
+            &buffer,
+            5,
+            false,
+            Mode::Normal,
+        );
+        assert_eq!(buffer.offset_of_line(6) + 3, region.end);
+
+        let region = Movement::PageUp(4).update_region(
+            &region,
+            &buffer,
+            1,
+            false,
+            Mode::Normal,
+        );
+        assert_eq!(buffer.offset_of_line(2) + 3, region.end);
+        let region = Movement::PageUp(4).update_region(
+            &region,
+            &buffer,
+            1,
+            false,
+            Mode::Normal,
+        );
+        assert_eq!(3, region.end);
+    }
+
+    #[test]
+    fn test_line_keeps_column_for_up_and_down() {
+        let buffer = Buffer::new("abcdef\nabcdef\na");
+        let movement = |movement: Movement, region: &SelRegion| {
+            movement.update_region(region, &buffer, 1, false, Mode::Normal)
+        };
+
+        let region =
+            movement(Movement::Line(LinePosition::Last), &SelRegion::caret(4));
+        assert_eq!(
+            (buffer.offset_of_line(2), Some(ColPosition::Col(4.0))),
+            (region.end, region.horiz)
+        );
+        let region = movement(Movement::Up, &region);
+        assert_eq!(
+            (buffer.offset_of_line(1) + 4, Some(ColPosition::Col(4.0))),
+            (region.end, region.horiz)
+        );
+
+        let region = movement(Movement::Line(LinePosition::First), &region);
+        let region = movement(Movement::Down, &region);
+        assert_eq!(
+            (buffer.offset_of_line(1) + 4, Some(ColPosition::Col(4.0))),
+            (region.end, region.horiz)
+        );
+    }
+
+    #[test]
+    fn test_update_region_with_goal() {
+        let buffer = Buffer::new("abcdef\nab\nabcdef");
+        let mut goal_col = None;
+        let mut movement = |movement: Movement, region: &SelRegion| {
+            movement.update_region_with_goal(
+                region,
+                &buffer,
+                1,
+                false,
+                Mode::Insert,
+                &mut goal_col,
+            )
+        };
+
+        let region = movement(Movement::Down, &SelRegion::caret(5));
+        assert_eq!(region, SelRegion::caret(9));
+        let region = movement(Movement::Down, &region);
+        assert_eq!(region, SelRegion::caret(15));
+        let region = movement(Movement::Left, &region);
+        assert_eq!(region, SelRegion::caret(14));
+        let region = movement(Movement::Up, &region);
+        assert_eq!(region, SelRegion::caret(9));
+        let region = movement(Movement::Up, &region);
+        assert_eq!(region, SelRegion::caret(4));
+        assert_eq!(goal_col, Some(4));
+    }
+
+    #[test]
+    fn test_first_non_blank() {
+        //                        01234 5678 90123
+        let buffer = Buffer::new("  ab\n   \n    c");
+        let movement = |movement: Movement, region: &SelRegion| {
+            movement.update_region(region, &buffer, 1, false, Mode::Normal)
+        };
+
+        let region = movement(Movement::FirstNonBlank, &SelRegion::caret(3));
+        assert_eq!(
+            region,
+            SelRegion::new(2, 2, Some(ColPosition::FirstNonBlank))
+        );
+
+        // Moving down keeps to the first non-blank character of each line,
+        // or the last column of a line of only whitespace
+        let region = movement(Movement::Down, &region);
+        assert_eq!(
+            region,
+            SelRegion::new(7, 7, Some(ColPosition::FirstNonBlank))
+        );
+        let region = movement(Movement::Down, &region);
+        assert_eq!(
+            region,
+            SelRegion::new(13, 13, Some(ColPosition::FirstNonBlank))
+        );
+
+        let region = movement(Movement::FirstNonBlank, &SelRegion::caret(5));
+        assert_eq!(
+            region,
+            SelRegion::new(7, 7, Some(ColPosition::FirstNonBlank))
+        );
+    }
+
+    #[test]
+    fn test_smart_home() {
+        //                        012345 678
+        let buffer = Buffer::new("  abc\n  d");
+        let movement = |movement: Movement, region: &SelRegion| {
+            movement.update_region(region, &buffer, 1, false, Mode::Normal)
+        };
+
+        let region = movement(Movement::SmartHome, &SelRegion::caret(4));
+        assert_eq!(
+            region,
+            SelRegion::new(2, 2, Some(ColPosition::FirstNonBlank))
+        );
+        let region = movement(Movement::SmartHome, &region);
+        assert_eq!(region, SelRegion::new(0, 0, Some(ColPosition::Start)));
+        let region = movement(Movement::SmartHome, &region);
+        assert_eq!(
+            region,
+            SelRegion::new(2, 2, Some(ColPosition::FirstNonBlank))
+        );
+
+        // Before the first non-blank character goes to it too
+        let region = movement(Movement::SmartHome, &SelRegion::caret(7));
+        assert_eq!(
+            region,
+            SelRegion::new(8, 8, Some(ColPosition::FirstNonBlank))
+        );
+
+        // The chosen position sticks for vertical moves
+        let region = movement(Movement::Up, &region);
+        assert_eq!(
+            region,
+            SelRegion::new(2, 2, Some(ColPosition::FirstNonBlank))
+        );
+        let region = movement(Movement::SmartHome, &region);
+        let region = movement(Movement::Down, &region);
+        assert_eq!(region, SelRegion::new(6, 6, Some(ColPosition::Start)));
+    }
+
+    #[test]
+    fn test_effective_last_line() {
+        //                        012 3456 789
+        let buffer = Buffer::new("ab\n cd\nef\n");
+        let line = |movement: Movement, offset, count| {
+            let (offset, _) =
+                movement.move_offset(&buffer, offset, None, count, Mode::Normal);
+            buffer.line_of_offset(offset)
+        };
+
+        // None of the line motions go to the empty line after the last newline
+        assert_eq!(2, line(Movement::Line(LinePosition::Last), 0, 1));
+        assert_eq!(2, line(Movement::GotoLine(100), 0, 1));
+        assert_eq!(2, line(Movement::Line(LinePosition::Line(100)), 0, 1));
+        assert_eq!(2, line(Movement::Down, 0, 1000));
+        assert_eq!(2, line(Movement::PageDown(10), 0, 1));
+
+        // Nor do they move up from it
+        assert_eq!(3, line(Movement::Down, 10, 1));
+    }
+
+    #[test]
+    fn test_is_vertical() {
+        assert!(Movement::Down.is_vertical());
+        assert!(Movement::DisplayUp.is_vertical());
+        assert!(Movement::PageDown(10).is_vertical());
+        assert!(Movement::HalfPageUp(10).is_vertical());
+        assert!(Movement::GotoLine(1).is_vertical());
+        assert!(!Movement::Right.is_vertical());
+        assert!(!Movement::WordForward.is_vertical());
+    }
+
+    #[test]
+    fn test_insert_mode_last_line() {
+        //                        012 3456 789
+        let buffer = Buffer::new("ab\n cd\nef\n");
+        let line = |movement: Movement, offset, count| {
+            let (offset, _) =
+                movement.move_offset(&buffer, offset, None, count, Mode::Insert);
+            buffer.line_of_offset(offset)
+        };
+
+        // The empty line after the last newline can be typed on in Insert
+        // mode, so line motions go there
+        assert_eq!(3, line(Movement::Down, 7, 1));
+        assert_eq!(3, line(Movement::Down, 0, 1000));
+        assert_eq!(3, line(Movement::PageDown(10), 0, 1));
+        assert_eq!(3, line(Movement::Line(LinePosition::Last), 0, 1));
+        assert_eq!(3, line(Movement::Line(LinePosition::Relative(10)), 0, 1));
+        assert_eq!(3, line(Movement::GotoLine(100), 0, 1));
+        assert_eq!(
+            3,
+            buffer.line_of_offset(
+                Movement::Down
+                    .move_offset_with_folds(&buffer, &[], 7, None, 1, Mode::Insert)
+                    .0
+            )
+        );
+    }
+
+    #[test]
+    fn test_relative_line() {
+        let buffer = Buffer::new("abcdef\nab\nabcdef\nabcdef\n");
+        let movement = |delta, region: &SelRegion| {
+            Movement::Line(LinePosition::Relative(delta)).update_region(
+                region,
+                &buffer,
+                1,
+                false,
+                Mode::Normal,
+            )
+        };
+
+        let region = movement(2, &SelRegion::caret(4));
+        assert_eq!(buffer.offset_to_line_col(region.end), (2, 4));
+        let region = movement(-1, &region);
+        assert_eq!(buffer.offset_to_line_col(region.end), (1, 1));
+        let region = movement(1, &region);
+        assert_eq!(buffer.offset_to_line_col(region.end), (2, 4));
+        assert_eq!(region.horiz, Some(ColPosition::Col(4.0)));
+
+        // Clamped to the first line and the last one before the final newline
+        let region = movement(-10, &region);
+        assert_eq!(buffer.offset_to_line_col(region.end), (0, 4));
+        let region = movement(10, &region);
+        assert_eq!(buffer.offset_to_line_col(region.end), (3, 4));
+        let region = movement(0, &region);
+        assert_eq!(buffer.offset_to_line_col(region.end), (3, 4));
+    }
+
+    #[test]
+    fn test_extend_in_insert_mode() {
+        //                        012345678901 23456
+        let buffer = Buffer::new("one two six\nthree");
+        let movement = |movement: Movement, region: &SelRegion, modify| {
+            movement.update_region(region, &buffer, 1, modify, Mode::Insert)
+        };
+
+        // Shift with any movement keeps the anchor
+        let region = movement(Movement::WordForward, &SelRegion::caret(4), true);
+        assert_eq!((region.start, region.end), (4, 8));
+        let region = movement(Movement::EndOfLine, &region, true);
+        assert_eq!((region.start, region.end), (4, 11));
+        let region = movement(Movement::Down, &region, true);
+        assert_eq!((region.start, region.end), (4, 17));
+        let region = movement(Movement::WordBackward, &region, true);
+        assert_eq!((region.start, region.end), (4, 12));
+        let region = movement(Movement::StartOfLine, &region, true);
+        assert_eq!((region.start, region.end), (4, 12));
+        let region = movement(Movement::Up, &region, true);
+        assert_eq!((region.start, region.end), (4, 0));
+
+        // Without it, the region collapses to the new cursor
+        let region = movement(Movement::Right, &region, false);
+        assert!(region.is_caret());
+        assert_eq!(region.end, 1);
+    }
+
+    #[test]
+    fn test_document_start_and_end() {
+        //                        012345 67890
+        let buffer = Buffer::new("  abc\n  de\n");
+        let movement = |movement: Movement, offset, mode| {
+            movement.move_offset(&buffer, offset, None, 1, mode)
+        };
+
+        assert_eq!(
+            (0, Some(ColPosition::Start)),
+            movement(Movement::DocumentStart, 8, Mode::Normal)
+        );
+        assert_eq!(
+            (11, Some(ColPosition::End)),
+            movement(Movement::DocumentEnd, 0, Mode::Insert)
+        );
+        assert_eq!(
+            (11, Some(ColPosition::End)),
+            movement(Movement::DocumentEnd, 0, Mode::Normal)
+        );
+        // `G` stays on the last line with text
+        assert_eq!(
+            (8, Some(ColPosition::FirstNonBlank)),
+            movement(Movement::GotoLine(100), 0, Mode::Normal)
+        );
+
+        let buffer = Buffer::new("ab\ncd");
+        assert_eq!(
+            (4, Some(ColPosition::End)),
+            Movement::DocumentEnd.move_offset(&buffer, 0, None, 1, Mode::Normal)
+        );
+        assert_eq!(
+            (5, Some(ColPosition::End)),
+            Movement::DocumentEnd.move_offset(&buffer, 0, None, 1, Mode::Insert)
+        );
+    }
+
+    #[test]
+    fn test_half_page_move() {
+        let buffer = Buffer::new(&"abcdef\n".repeat(40));
+        let movement = |movement: Movement, region: &SelRegion| {
+            movement.update_region(region, &buffer, 1, false, Mode::Normal)
+        };
+
+        let start = SelRegion::caret(buffer.offset_of_line(20) + 3);
+        let region = movement(Movement::HalfPageDown(15), &start);
+        assert_eq!(27, buffer.line_of_offset(region.end));
+        let region = movement(Movement::HalfPageUp(15), &region);
+        assert_eq!(
+            (start.end, Some(ColPosition::Col(3.0))),
+            (region.end, region.horiz)
+        );
+
+        // Never less than a line
+        let region = movement(Movement::HalfPageUp(1), &region);
+        assert_eq!(19, buffer.line_of_offset(region.end));
+    }
+
+    #[test]
+    fn test_column_goto() {
+        // "中" and "文" are three bytes each, at 4 and 7
+        let buffer = Buffer::new("abc\n中文x\n");
+        let movement = |col, offset, mode| {
+            Movement::ColumnGoto(col).move_offset(&buffer, offset, None, 1, mode)
+        };
+
+        assert_eq!(
+            (1, Some(ColPosition::Col(1.0))),
+            movement(1, 3, Mode::Normal)
+        );
+        // Past the end of the line
+        assert_eq!(
+            (2, Some(ColPosition::Col(2.0))),
+            movement(10, 0, Mode::Normal)
+        );
+        assert_eq!(
+            (3, Some(ColPosition::Col(3.0))),
+            movement(10, 0, Mode::Insert)
+        );
+        // Wide chars take up two columns
+        assert_eq!(
+            (7, Some(ColPosition::Col(2.0))),
+            movement(2, 4, Mode::Normal)
+        );
+        assert_eq!(
+            (7, Some(ColPosition::Col(2.0))),
+            movement(3, 4, Mode::Normal)
+        );
+        assert_eq!(
+            (10, Some(ColPosition::Col(4.0))),
+            movement(4, 4, Mode::Normal)
+        );
+        assert_eq!(
+            (11, Some(ColPosition::Col(5.0))),
+            movement(9, 4, Mode::Insert)
+        );
+    }
+
+    #[test]
+    fn test_visual_block() {
+        let buffer = Buffer::new("abcdef\nabcdefgh\nab");
+        //                      ->0123456 789012345 67<-
+        let movement = |movement: Movement, selection: &Selection, modify| {
+            movement.update_selection(
+                selection,
+                &buffer,
+                1,
+                modify,
+                Mode::VisualBlock,
+            )
+        };
+
+        let selection = movement(Movement::Right, &Selection::caret(9), true);
+        assert_eq!(selection.regions(), &[SelRegion::new(9, 10, None)]);
+        let selection = movement(Movement::Up, &selection, true);
+        assert_eq!(
+            selection.regions(),
+            &[
+                SelRegion::new(2, 3, Some(ColPosition::Col(3.0))),
+                SelRegion::new(9, 10, None),
+            ]
+        );
+        assert_eq!(selection.last_inserted(), selection.first());
+
+        // Moving back below the anchor turns the block around
+        let selection = movement(Movement::Down, &selection, true);
+        assert_eq!(
+            selection.regions(),
+            &[SelRegion::new(9, 10, Some(ColPosition::Col(3.0)))]
+        );
+        let selection = movement(Movement::Up, &selection, false);
+        assert_eq!(
+            selection.regions(),
+            &[SelRegion::new(3, 3, Some(ColPosition::Col(3.0)))]
+        );
+    }
+
+    #[test]
+    fn test_crlf() {
+        let buffer = Buffer::new("ab\r\ncd\r\n");
+        //                      ->01 2 3 45 6 7 <-
+        let movement = |movement: Movement, offset, mode| {
+            movement.move_offset(&buffer, offset, None, 1, mode).0
+        };
+
+        assert_eq!(1, movement(Movement::EndOfLine, 0, Mode::Normal));
+        assert_eq!(2, movement(Movement::EndOfLine, 0, Mode::Insert));
+        assert_eq!(6, movement(Movement::EndOfLine, 4, Mode::Insert));
+
+        assert_eq!(1, movement(Movement::Right, 1, Mode::Normal));
+        assert_eq!(4, movement(Movement::Right, 2, Mode::Insert));
+        assert_eq!(8, movement(Movement::Right, 6, Mode::Insert));
+        assert_eq!(2, movement(Movement::Left, 4, Mode::Insert));
+    }
+
+    #[test]
+    fn test_word_end_forward() {
+        let buffer = Buffer::new("one two\nthree four");
+        //                      ->01234567 8901234567<-
+        let movement = |offset, count, mode| {
+            Movement::WordEndForward.move_offset(&buffer, offset, None, count, mode)
+        };
+
+        assert_eq!(
+            (2, Some(ColPosition::Col(2.0))),
+            movement(0, 1, Mode::Normal)
+        );
+        // Already on a word end moves on to the next one
+        assert_eq!(
+            (6, Some(ColPosition::Col(6.0))),
+            movement(2, 1, Mode::Normal)
+        );
+        assert_eq!(
+            (12, Some(ColPosition::Col(4.0))),
+            movement(2, 2, Mode::Normal)
+        );
+        // Insert mode ends up after the last char instead
+        assert_eq!(
+            (3, Some(ColPosition::Col(3.0))),
+            movement(0, 1, Mode::Insert)
+        );
+        assert_eq!(
+            (7, Some(ColPosition::Col(7.0))),
+            movement(3, 1, Mode::Insert)
+        );
+    }
+
+    #[test]
+    fn test_subword() {
+        let buffer = Buffer::new("fooBarBaz\nfoo_bar HTTPRequest");
+        //                      ->0123456789 0123456789012345678<-
+        let movement = |movement: Movement, offset, count| {
+            movement.move_offset(&buffer, offset, None, count, Mode::Normal)
+        };
+
+        assert_eq!(
+            (3, Some(ColPosition::Col(3.0))),
+            movement(Movement::SubwordForward, 0, 1)
+        );
+        assert_eq!(
+            (14, Some(ColPosition::Col(4.0))),
+            movement(Movement::SubwordForward, 6, 2)
+        );
+        assert_eq!(
+            (18, Some(ColPosition::Col(8.0))),
+            movement(Movement::SubwordForward, 14, 1)
+        );
+        assert_eq!(
+            (22, Some(ColPosition::Col(12.0))),
+            movement(Movement::SubwordForward, 18, 1)
+        );
+
+        assert_eq!(
+            (18, Some(ColPosition::Col(8.0))),
+            movement(Movement::SubwordBackward, 22, 1)
+        );
+        assert_eq!(
+            (14, Some(ColPosition::Col(4.0))),
+            movement(Movement::SubwordBackward, 18, 1)
+        );
+        assert_eq!(
+            (6, Some(ColPosition::Col(6.0))),
+            movement(Movement::SubwordBackward, 10, 1)
+        );
+        assert_eq!(
+            (0, Some(ColPosition::Col(0.0))),
+            movement(Movement::SubwordBackward, 10, 10)
+        );
+    }
 }
@@ -1,8 +1,18 @@
+use lsp_types::TextEdit;
 use serde::{Deserialize, Serialize};
-use std::cmp::{max, min, Ordering};
-use xi_rope::{RopeDelta, Transformer};
+use std::{
+    cmp::{max, min, Ordering},
+    collections::BTreeSet,
+};
+use xi_rope::{RopeDelta, RopeInfo, Transformer};
 
-use crate::cursor::ColPosition;
+use crate::{
+    buffer::Buffer,
+    cursor::ColPosition,
+    mode::Mode,
+    movement::Movement,
+    word::{get_word_property, WordProperty},
+};
 
 #[derive(Copy, Clone)]
 pub enum InsertDrift {
@@ -46,10 +56,6 @@ pub fn caret(offset: usize) -> SelRegion {
         }
     }
 
-    fn contains(&self, offset: usize) -> bool {
-        self.min() <= offset && offset <= self.max()
-    }
-
     pub fn min(self) -> usize {
         min(self.start, self.end)
     }
@@ -70,6 +76,17 @@ pub fn is_caret(self) -> bool {
         self.start == self.end
     }
 
+    /// The same region with its ends swapped, keeping `horiz`. A caret stays
+    /// the same.
+    pub fn reverse(self) -> SelRegion {
+        SelRegion::new(self.end, self.start, self.horiz)
+    }
+
+    /// Whether the region is a caret at `offset`.
+    pub fn is_at(self, offset: usize) -> bool {
+        self.is_caret() && self.end == offset
+    }
+
     fn should_merge(self, other: SelRegion) -> bool {
         other.min() < self.max()
             || ((self.is_caret() || other.is_caret()) && other.min() == self.max())
@@ -114,13 +131,75 @@ pub fn region(start: usize, end: usize) -> Selection {
         }
     }
 
-    pub fn contains(&self, offset: usize) -> bool {
-        for region in self.regions.iter() {
-            if region.contains(offset) {
-                return true;
+    /// A single region over the whole buffer, with the cursor at the end.
+    /// The horizontal position is the column of the end, so that a
+    /// following vertical move starts from there.
+    pub fn select_all(buffer: &Buffer) -> Selection {
+        let end = buffer.len();
+        let horiz = Some(ColPosition::Col(buffer.display_col_of_offset(end) as f64));
+        Selection {
+            regions: vec![SelRegion::new(0, end, horiz)],
+            last_inserted: 0,
+        }
+    }
+
+    /// A block selection between `anchor` and `cursor`, like in Visual Block
+    /// mode, with a region on each line between them that goes from the
+    /// anchor's column to the cursor's column. The region on the cursor's
+    /// line is the primary one.
+    ///
+    /// Lines too short to reach both columns are clamped to their end, or
+    /// left out with `skip_short_lines`. The anchor's and the cursor's lines
+    /// always get a region, so that the block can be told from the selection.
+    pub fn from_block(
+        buffer: &Buffer,
+        anchor: usize,
+        cursor: usize,
+        skip_short_lines: bool,
+    ) -> Selection {
+        let (anchor_line, anchor_col) = buffer.offset_to_line_col(anchor);
+        let (cursor_line, cursor_col) = buffer.offset_to_line_col(cursor);
+        let right_col = anchor_col.max(cursor_col);
+
+        let mut regions = Vec::new();
+        for line in anchor_line.min(cursor_line)..=anchor_line.max(cursor_line) {
+            let line_end_col = buffer.line_end_col(line, true);
+            if skip_short_lines
+                && line_end_col < right_col
+                && line != anchor_line
+                && line != cursor_line
+            {
+                continue;
             }
+            let start =
+                buffer.offset_of_line_col(line, anchor_col.min(line_end_col));
+            let end = buffer.offset_of_line_col(line, cursor_col.min(line_end_col));
+            regions.push(SelRegion::new(start, end, None));
+        }
+        let last_inserted = if cursor_line < anchor_line {
+            0
+        } else {
+            regions.len() - 1
+        };
+        Selection {
+            regions,
+            last_inserted,
         }
-        false
+    }
+
+    /// Whether `offset` is selected, that is in `min..max` of a region, so
+    /// carets don't select anything.
+    pub fn contains(&self, offset: usize) -> bool {
+        self.region_at(offset).is_some()
+    }
+
+    /// The region whose `min..max` covers `offset`, found by binary search
+    /// since the regions are sorted and don't overlap.
+    pub fn region_at(&self, offset: usize) -> Option<&SelRegion> {
+        let ix = self
+            .regions
+            .partition_point(|region| region.max() <= offset);
+        self.regions.get(ix).filter(|region| region.min() <= offset)
     }
 
     pub fn regions(&self) -> &[SelRegion] {
@@ -131,6 +210,13 @@ pub fn regions_mut(&mut self) -> &mut [SelRegion] {
         &mut self.regions
     }
 
+    /// Iterate over the regions, in order.
+    pub fn iter(&self) -> std::slice::Iter<'_, SelRegion> {
+        self.regions.iter()
+    }
+
+    /// A caret at the start of each region. For the leftmost offset of the
+    /// whole selection, see [`Selection::min_offset`].
     pub fn min(&self) -> Selection {
         let mut selection = Self::new();
         for region in &self.regions {
@@ -161,6 +247,20 @@ pub fn last_inserted(&self) -> Option<&SelRegion> {
         Some(&self.regions[self.last_inserted])
     }
 
+    /// The index of the primary region, the one the user placed last, in
+    /// the sorted regions. It's kept up to date by [`Selection::add_region`],
+    /// pointing at the merged region when the added one got merged.
+    pub fn primary_index(&self) -> usize {
+        self.last_inserted
+    }
+
+    /// The primary region, same as [`Selection::last_inserted`].
+    ///
+    /// Panics if the selection is empty.
+    pub fn primary(&self) -> &SelRegion {
+        &self.regions[self.last_inserted]
+    }
+
     pub fn last_inserted_mut(&mut self) -> Option<&mut SelRegion> {
         if self.is_empty() {
             return None;
@@ -185,6 +285,25 @@ pub fn is_empty(&self) -> bool {
         self.len() == 0
     }
 
+    /// The number of chars selected by all the regions together, like for
+    /// showing "N selected" in the status bar. Carets don't count.
+    pub fn selected_len(&self, buffer: &Buffer) -> usize {
+        self.regions
+            .iter()
+            .map(|region| {
+                buffer.char_indices_iter(region.min()..region.max()).count()
+            })
+            .sum()
+    }
+
+    /// Whether one of the regions is a caret at the very end of the buffer.
+    pub fn has_caret_at_end(&self, buffer: &Buffer) -> bool {
+        self.regions
+            .last()
+            .map(|region| region.is_at(buffer.len()))
+            .unwrap_or(false)
+    }
+
     pub fn min_offset(&self) -> usize {
         let mut offset = self.regions()[0].min();
         for region in &self.regions {
@@ -260,6 +379,104 @@ pub fn delete_range(&mut self, start: usize, end: usize, delete_adjacent: bool)
         remove_n_at(&mut self.regions, first, last - first);
     }
 
+    /// Remove `start..end` from the selection, trimming the regions that
+    /// overlap it, or splitting them in two where it's in their middle.
+    /// Carets in the range are removed too. The primary region stays the one
+    /// with the primary cursor, or the one next to it if that was removed.
+    pub fn subtract_region(&mut self, start: usize, end: usize) {
+        if start >= end {
+            return;
+        }
+        let mut regions = Vec::with_capacity(self.regions.len() + 1);
+        let mut last_inserted = None;
+        for (ix, region) in self.regions.iter().enumerate() {
+            let (min, max) = (region.min(), region.max());
+            let overlaps = if region.is_caret() {
+                start <= min && min < end
+            } else {
+                min < end && start < max
+            };
+            if !overlaps {
+                if ix == self.last_inserted {
+                    last_inserted = Some(regions.len());
+                }
+                regions.push(*region);
+                continue;
+            }
+
+            let forward = region.start <= region.end;
+            let before = (min < start).then(|| {
+                if forward {
+                    SelRegion::new(min, start, None)
+                } else {
+                    SelRegion::new(start, min, region.horiz)
+                }
+            });
+            let after = (end < max).then(|| {
+                if forward {
+                    SelRegion::new(end, max, region.horiz)
+                } else {
+                    SelRegion::new(max, end, None)
+                }
+            });
+            if ix == self.last_inserted {
+                // The half with the cursor, or whatever is left of it
+                let cursor_half = regions.len()
+                    + usize::from(forward && before.is_some() && after.is_some());
+                last_inserted = Some(cursor_half);
+            }
+            regions.extend(before);
+            regions.extend(after);
+        }
+        self.last_inserted = last_inserted
+            .unwrap_or(self.last_inserted)
+            .min(regions.len().saturating_sub(1));
+        self.regions = regions;
+    }
+
+    /// Remove each of `ranges` from the selection, like
+    /// [`Selection::subtract_region`] does for a single range, for things like
+    /// leaving the comments out of a selection.
+    pub fn subtract_ranges(&self, ranges: &[(usize, usize)]) -> Selection {
+        let mut selection = self.clone();
+        for &(start, end) in ranges {
+            selection.subtract_region(start, end);
+        }
+        selection
+    }
+
+    /// The parts of the regions within `start..end`, unlike
+    /// [`Selection::regions_in_range`] cutting off what is outside of it.
+    /// Regions keep their direction, and their horiz if their cursor is still
+    /// in the range. Carets are kept if they are in the range.
+    pub fn intersect_range(&self, start: usize, end: usize) -> Selection {
+        let mut selection = Selection::new();
+        let mut last_inserted = None;
+        for (ix, region) in self.regions.iter().enumerate() {
+            let (min, max) = (region.min(), region.max());
+            let clipped = if region.is_caret() {
+                (start <= min && min < end).then_some(*region)
+            } else {
+                let (lo, hi) = (min.max(start), max.min(end));
+                (lo < hi).then(|| {
+                    if region.start <= region.end {
+                        SelRegion::new(lo, hi, region.horiz.filter(|_| hi == max))
+                    } else {
+                        SelRegion::new(hi, lo, region.horiz.filter(|_| lo == min))
+                    }
+                })
+            };
+            if ix == self.last_inserted {
+                last_inserted = Some(selection.regions.len());
+            }
+            selection.regions.extend(clipped);
+        }
+        selection.last_inserted = last_inserted
+            .unwrap_or(0)
+            .min(selection.regions.len().saturating_sub(1));
+        selection
+    }
+
     pub fn add_region(&mut self, region: SelRegion) {
         let mut ix = self.search(region.min());
         if ix == self.regions.len() {
@@ -289,7 +506,30 @@ pub fn add_region(&mut self, region: SelRegion) {
         } else {
             self.regions[ix] = region;
             remove_n_at(&mut self.regions, ix + 1, end_ix - ix - 1);
+            self.last_inserted = ix;
+        }
+    }
+
+    /// The index pairs of `regions` that overlap, which would be merged into
+    /// one when added to a selection. Each pair is given as `(i, j)` with
+    /// `i < j`, so that malformed selections from outside can be reported.
+    pub fn find_overlaps(regions: &[SelRegion]) -> Vec<(usize, usize)> {
+        let mut sorted: Vec<usize> = (0..regions.len()).collect();
+        sorted.sort_by_key(|&i| regions[i].min());
+
+        let mut overlaps = Vec::new();
+        for (n, &i) in sorted.iter().enumerate() {
+            for &j in &sorted[n + 1..] {
+                if regions[j].min() > regions[i].max() {
+                    break;
+                }
+                if regions[i].should_merge(regions[j]) {
+                    overlaps.push((i.min(j), i.max(j)));
+                }
+            }
         }
+        overlaps.sort_unstable();
+        overlaps
     }
 
     pub fn apply_delta(
@@ -297,65 +537,2686 @@ pub fn apply_delta(
         delta: &RopeDelta,
         after: bool,
         drift: InsertDrift,
+    ) -> Selection {
+        // A single caret is the common case while typing, and it can't merge
+        // with anything.
+        if let [region] = self.regions.as_slice() {
+            let mut transformer = Transformer::new(delta);
+            return Selection {
+                regions: vec![transform_region(
+                    &mut transformer,
+                    region,
+                    after,
+                    drift,
+                )],
+                last_inserted: 0,
+            };
+        }
+        self.apply_delta_to_regions(delta, after, drift)
+    }
+
+    fn apply_delta_to_regions(
+        &self,
+        delta: &RopeDelta,
+        after: bool,
+        drift: InsertDrift,
+    ) -> Selection {
+        let mut transformer = Transformer::new(delta);
+        let mut result = Selection {
+            regions: self
+                .regions()
+                .iter()
+                .map(|region| {
+                    transform_region(&mut transformer, region, after, drift)
+                })
+                .collect(),
+            last_inserted: self.last_inserted,
+        };
+        result.dedupe_and_sort();
+        result
+    }
+
+    /// Sort the regions and merge the ones that overlap or coincide, so that
+    /// the selection is valid again after its regions were moved around.
+    /// The primary region keeps pointing at whatever region it ended up in.
+    fn dedupe_and_sort(&mut self) {
+        let regions = self
+            .regions
+            .drain(..)
+            .map(|region| SelRegionWithData { region, data: () })
+            .collect();
+        let (regions, last_inserted) =
+            merge_regions(regions, self.last_inserted, |_, _| ());
+        self.regions = regions.into_iter().map(|r| r.region).collect();
+        self.last_inserted = last_inserted;
+    }
+
+    /// Same as [`Selection::apply_delta`], but regions that the edit brings
+    /// together are not merged, so the number of regions stays the same.
+    ///
+    /// This is opt-in because the result can hold coincident or overlapping
+    /// regions, which most of the selection code assumes can't happen.
+    pub fn apply_delta_without_merge(
+        &self,
+        delta: &RopeDelta,
+        after: bool,
+        drift: InsertDrift,
     ) -> Selection {
         let mut result = Selection::new();
         let mut transformer = Transformer::new(delta);
-        for region in self.regions() {
-            let is_caret = region.start == region.end;
-            let is_region_forward = region.start < region.end;
+        for (i, region) in self.regions().iter().enumerate() {
+            let region = transform_region(&mut transformer, region, after, drift);
+            let ix = result.regions.partition_point(|r| r.min() <= region.min());
+            result.regions.insert(ix, region);
+            if i == self.last_inserted {
+                result.last_inserted = ix;
+            } else if i > self.last_inserted && ix <= result.last_inserted {
+                // Inserted in front of the primary region, which moves it along
+                result.last_inserted += 1;
+            }
+        }
+        result
+    }
+
+    /// Same as [`Selection::apply_delta`], but also returns the cursor offset
+    /// of each resulting region, in order, for placing things like the
+    /// completion or IME popup after the edit.
+    pub fn apply_delta_carets(
+        &self,
+        delta: &RopeDelta,
+        after: bool,
+        drift: InsertDrift,
+    ) -> (Selection, Vec<usize>) {
+        let selection = self.apply_delta(delta, after, drift);
+        let carets = selection.regions.iter().map(|region| region.end).collect();
+        (selection, carets)
+    }
+
+    /// The range an operator like `d` or `y` acts on when combined with
+    /// `movement`, one for each region: from the cursor to where the movement
+    /// goes, including the character there if the movement is inclusive.
+    pub fn range_over_motion(
+        &self,
+        movement: &Movement,
+        buffer: &Buffer,
+        count: usize,
+        mode: Mode,
+    ) -> Vec<(usize, usize)> {
+        self.regions
+            .iter()
+            .map(|region| {
+                if let Movement::FirstNonBlank = movement {
+                    // The movement goes to the line start when the cursor is
+                    // in the indentation, but `d^` only ever deletes back to
+                    // the first non-blank character.
+                    let line = buffer.line_of_offset(region.end);
+                    let non_blank = buffer.first_non_blank_character_on_line(line);
+                    return (non_blank.min(region.end), region.end);
+                }
+
+                let (target, _) = movement.move_offset(
+                    buffer,
+                    region.end,
+                    region.horiz.as_ref(),
+                    count,
+                    mode,
+                );
+                if target < region.end {
+                    (target, region.end)
+                } else if movement.is_inclusive() {
+                    (
+                        region.end,
+                        buffer.next_grapheme_offset(target, 1, buffer.len()),
+                    )
+                } else {
+                    (region.end, target)
+                }
+            })
+            .collect()
+    }
 
-            let (start_after, end_after) = match (drift, is_caret) {
-                (InsertDrift::Inside, false) => {
-                    (!is_region_forward, is_region_forward)
+    /// The column spans of the regions on `line`, for drawing the selection
+    /// one line at a time. A region that goes past the line covers it up to
+    /// and including the newline. Carets give an empty span.
+    pub fn line_spans(&self, buffer: &Buffer, line: usize) -> Vec<(usize, usize)> {
+        let line_start = buffer.offset_of_line(line);
+        let line_end = buffer.offset_of_line(line + 1);
+        self.regions_in_range(line_start, line_end)
+            .iter()
+            .filter_map(|region| {
+                let (start_line, start_col) =
+                    buffer.offset_to_line_col(region.min());
+                let (end_line, end_col) = buffer.offset_to_line_col(region.max());
+                if start_line > line || end_line < line {
+                    return None;
                 }
-                (InsertDrift::Outside, false) => {
-                    (is_region_forward, !is_region_forward)
+                let start_col = if start_line < line { 0 } else { start_col };
+                let end_col = if end_line > line {
+                    buffer.line_len(line)
+                } else {
+                    end_col
+                };
+                if start_col == end_col && !region.is_caret() {
+                    return None;
                 }
-                _ => (after, after),
+                Some((start_col, end_col))
+            })
+            .collect()
+    }
+
+    /// Grow each region to cover the whole sentences it touches, see
+    /// [`Buffer::sentences_around`]. Regions that only touch the whitespace
+    /// between sentences are kept as is.
+    pub fn expand_to_sentences(&self, buffer: &Buffer) -> Selection {
+        let mut selection = Selection {
+            regions: self
+                .regions
+                .iter()
+                .map(|region| {
+                    let (min, max) = (region.min(), region.max());
+                    let touched: Vec<(usize, usize)> = buffer
+                        .sentences_around(min, max)
+                        .into_iter()
+                        .filter(|(start, end)| *start <= max && min <= *end)
+                        .collect();
+                    match (touched.first(), touched.last()) {
+                        (Some((start, _)), Some((_, end))) => {
+                            SelRegion::new(*start, *end, None)
+                        }
+                        _ => *region,
+                    }
+                })
+                .collect(),
+            last_inserted: self.last_inserted,
+        };
+        selection.dedupe_and_sort();
+        selection
+    }
+
+    /// The selection when entering Visual mode, where each caret selects the
+    /// character under it, like `v` in Vim. A caret at the end of a line has
+    /// no character under it and stays a caret.
+    pub fn enter_visual(&self, buffer: &Buffer) -> Selection {
+        let mut selection = self.clone();
+        for region in selection.regions.iter_mut() {
+            if region.is_caret() {
+                let line = buffer.line_of_offset(region.end);
+                let line_end = buffer.line_end_offset(line, true);
+                region.end = buffer.next_grapheme_offset(region.start, 1, line_end);
+            }
+        }
+        selection
+    }
+
+    /// Add a caret for each region, mirroring its cursor around `pivot`,
+    /// for editing both sides of something symmetric at once. Mirrored
+    /// offsets are clamped to `0..=buffer_len`.
+    pub fn mirror_around(&self, pivot: usize, buffer_len: usize) -> Selection {
+        let mut selection = self.clone();
+        for region in &self.regions {
+            let offset = if region.end <= pivot {
+                pivot + (pivot - region.end)
+            } else {
+                pivot.saturating_sub(region.end - pivot)
             };
+            selection.add_region(SelRegion::caret(offset.min(buffer_len)));
+        }
+        selection
+    }
 
-            let new_region = SelRegion::new(
-                transformer.transform(region.start, start_after),
-                transformer.transform(region.end, end_after),
-                None,
+    /// Add a caret on the line above or below the cursor of each region, in
+    /// the same display column or the end of that line if it's shorter,
+    /// keeping the existing regions. Regions already on the first or last
+    /// line get no new caret. The caret added for the primary region becomes
+    /// the primary one.
+    pub fn add_cursor_vertical(&self, buffer: &Buffer, above: bool) -> Selection {
+        let (movement, edge_line) = if above {
+            (Movement::Up, 0)
+        } else {
+            (Movement::Down, buffer.last_line())
+        };
+        let new_caret = |region: &SelRegion| {
+            if buffer.line_of_offset(region.end) == edge_line {
+                return None;
+            }
+            let (offset, horiz) = movement.move_offset(
+                buffer,
+                region.end,
+                region.horiz.as_ref(),
+                1,
+                Mode::Insert,
             );
-            result.add_region(new_region);
+            Some(SelRegion::new(offset, offset, horiz))
+        };
+
+        let mut selection = self.clone();
+        for (ix, region) in self.regions.iter().enumerate() {
+            if ix != self.last_inserted {
+                if let Some(caret) = new_caret(region) {
+                    selection.add_region(caret);
+                }
+            }
         }
-        result
+        // Added last so that it becomes the last inserted region
+        if let Some(caret) = self.last_inserted().and_then(new_caret) {
+            selection.add_region(caret);
+        }
+        selection
     }
 
-    pub fn get_cursor_offset(&self) -> usize {
-        if self.is_empty() {
-            return 0;
+    /// Swap the ends of every region, like `o` in Visual mode in Vim. The
+    /// regions cover the same text, so their order doesn't change.
+    pub fn reverse_all(&self) -> Selection {
+        let mut selection = self.clone();
+        for region in selection.regions.iter_mut() {
+            *region = region.reverse();
         }
-        self.regions[self.last_inserted].end
+        selection
     }
 
-    pub fn replace_last_inserted_region(&mut self, region: SelRegion) {
-        if self.is_empty() {
-            self.add_region(region);
-            return;
+    /// Swap the ends of the primary region only, so that the other end can be
+    /// grown in Visual mode. The horizontal position is dropped, since it
+    /// belonged to the old end, and gets recomputed on the next vertical move.
+    pub fn toggle_primary_direction(&self) -> Selection {
+        let mut selection = self.clone();
+        if let Some(primary) = selection.last_inserted_mut() {
+            *primary = SelRegion::new(primary.end, primary.start, None);
         }
+        selection
+    }
 
-        self.regions.remove(self.last_inserted);
-        self.add_region(region);
+    /// Extend only the primary region by `movement`, keeping its start where
+    /// it is, like growing a selection with Shift outside of modal editing.
+    /// Unlike [`Movement::update_selection`], the other regions stay as they
+    /// are, unless the primary grows over them.
+    pub fn extend_primary_by(
+        &self,
+        movement: &Movement,
+        buffer: &Buffer,
+    ) -> Selection {
+        let mut selection = self.clone();
+        if let Some(primary) = selection.last_inserted_mut() {
+            *primary =
+                movement.update_region(primary, buffer, 1, true, Mode::Insert);
+            selection.dedupe_and_sort();
+        }
+        selection
     }
-}
 
-impl Default for Selection {
-    fn default() -> Self {
-        Self::new()
+    /// Move every region by `movement` once, extending the regions in the
+    /// Visual modes. Also returns whether any of the cursors couldn't move,
+    /// so that hitting the document edge can be signalled once for all of
+    /// them.
+    pub fn update_all(
+        &self,
+        movement: &Movement,
+        buffer: &Buffer,
+        mode: Mode,
+    ) -> (Selection, bool) {
+        let modify =
+            matches!(mode, Mode::Visual | Mode::VisualLine | Mode::VisualBlock);
+        let mut selection = Selection::new();
+        let mut hit_edge = false;
+        for region in &self.regions {
+            let result =
+                movement.update_region_with_result(region, buffer, 1, modify, mode);
+            hit_edge |= !result.moved;
+            selection.add_region(result.region);
+        }
+        (selection, hit_edge)
     }
-}
 
-fn remove_n_at<T>(v: &mut Vec<T>, index: usize, n: usize) {
-    match n.cmp(&1) {
-        Ordering::Equal => {
-            v.remove(index);
+    /// The first and last line of the primary region, for scrolling it into
+    /// view. When the region is taller than the `view_lines` the view can
+    /// show, only the line of its cursor is returned.
+    pub fn reveal_range(
+        &self,
+        buffer: &Buffer,
+        view_lines: usize,
+    ) -> (usize, usize) {
+        match self.last_inserted() {
+            Some(region) => {
+                let start = buffer.line_of_offset(region.min());
+                let end = buffer.line_of_offset(region.max());
+                if end - start < view_lines {
+                    (start, end)
+                } else {
+                    let line = buffer.line_of_offset(region.end);
+                    (line, line)
+                }
+            }
+            None => (0, 0),
         }
-        Ordering::Greater => {
-            v.drain(index..index + n);
+    }
+
+    /// The column the primary cursor is shown in, with tabs taking up to
+    /// `tab_width` columns and wide chars two, unlike the column from
+    /// [`Buffer::offset_to_line_col`].
+    pub fn primary_visual_column(&self, buffer: &Buffer, tab_width: usize) -> usize {
+        self.last_inserted()
+            .map(|region| buffer.visual_col_of_offset(region.end, tab_width))
+            .unwrap_or(0)
+    }
+
+    /// The lines that have a cursor on them, for marking them in the gutter.
+    pub fn caret_lines(&self, buffer: &Buffer) -> BTreeSet<usize> {
+        self.regions
+            .iter()
+            .map(|region| buffer.line_of_offset(region.end))
+            .collect()
+    }
+
+    /// The middle of each region, for placing things like popups. The middle
+    /// is moved back to the start of the grapheme it would split.
+    pub fn region_midpoints(&self, buffer: &Buffer) -> Vec<usize> {
+        self.regions
+            .iter()
+            .map(|region| {
+                let (min, max) = (region.min(), region.max());
+                buffer.prev_grapheme_boundary(min + (max - min) / 2)
+            })
+            .collect()
+    }
+
+    /// Move the ends of the regions that are in the middle of a grapheme out
+    /// to its boundaries, so that offsets from outside, like from the LSP,
+    /// don't split a grapheme. Carets go to the start of the grapheme, and
+    /// regions that end up overlapping are merged.
+    pub fn snap_to_graphemes(&self, buffer: &Buffer) -> Selection {
+        let snap = |region: &SelRegion| {
+            let min = buffer.prev_grapheme_boundary(region.min());
+            if region.is_caret() {
+                return SelRegion::new(min, min, region.horiz);
+            }
+            let max = buffer.next_grapheme_boundary(region.max());
+            if region.start < region.end {
+                SelRegion::new(min, max, region.horiz)
+            } else {
+                SelRegion::new(max, min, region.horiz)
+            }
+        };
+        let mut selection = Selection::new();
+        for (ix, region) in self.regions.iter().enumerate() {
+            if ix != self.last_inserted {
+                selection.add_region(snap(region));
+            }
         }
-        _ => (),
-    };
+        // Added last so that it stays the last inserted region
+        if let Some(region) = self.last_inserted() {
+            selection.add_region(snap(region));
+        }
+        selection
+    }
+
+    /// Replace every region with the regions `f` gives for it, which are
+    /// sorted and merged like added regions. The last region given for the
+    /// primary region becomes the primary.
+    pub fn flat_map_regions<I, F>(&self, mut f: F) -> Selection
+    where
+        I: IntoIterator<Item = SelRegion>,
+        F: FnMut(&SelRegion) -> I,
+    {
+        let mut selection = Selection::new();
+        let mut primary = Vec::new();
+        for (ix, region) in self.regions.iter().enumerate() {
+            if ix == self.last_inserted {
+                primary.extend(f(region));
+            } else {
+                for region in f(region) {
+                    selection.add_region(region);
+                }
+            }
+        }
+        // Added last so that the primary region stays the last inserted one
+        for region in primary {
+            selection.add_region(region);
+        }
+        selection
+    }
+
+    /// Split the region that `offset` is strictly inside of into two regions
+    /// that meet at `offset`, keeping the direction of the original region.
+    /// If it was the primary region, the half with its cursor becomes the
+    /// primary. `offset` must be on a char boundary.
+    pub fn split_at(&self, offset: usize) -> Selection {
+        let mut selection = self.clone();
+        let ix = match self
+            .regions
+            .iter()
+            .position(|region| region.min() < offset && offset < region.max())
+        {
+            Some(ix) => ix,
+            None => return selection,
+        };
+
+        let region = self.regions[ix];
+        let (first, second) = if region.start < region.end {
+            (
+                SelRegion::new(region.start, offset, None),
+                SelRegion::new(offset, region.end, region.horiz),
+            )
+        } else {
+            (
+                SelRegion::new(offset, region.end, region.horiz),
+                SelRegion::new(region.start, offset, None),
+            )
+        };
+        selection.regions[ix] = first;
+        selection.regions.insert(ix + 1, second);
+        if self.last_inserted > ix
+            || (self.last_inserted == ix && region.start < region.end)
+        {
+            selection.last_inserted += 1;
+        }
+        selection
+    }
+
+    /// The text to put on the clipboard when copying the selection, and
+    /// whether it is line-wise. Copies in VisualLine mode are line-wise, and
+    /// so are Insert mode copies with a caret, which copies its whole line.
+    /// The text of a line-wise copy always ends with a newline, so that
+    /// pasting it inserts new lines. Regions are joined with newlines.
+    pub fn clipboard_text(&self, buffer: &Buffer, mode: &Mode) -> (String, bool) {
+        let mut line_wise = *mode == Mode::VisualLine;
+        let mut content = String::new();
+        for region in &self.regions {
+            let region_content = if region.is_caret() && *mode == Mode::Insert {
+                line_wise = true;
+                buffer.line_content(buffer.line_of_offset(region.start))
+            } else {
+                buffer.slice_to_cow(region.min()..region.max())
+            };
+            if !content.is_empty() && !content.ends_with('\n') {
+                content.push('\n');
+            }
+            content += &region_content;
+        }
+        if line_wise && !content.ends_with('\n') {
+            content.push('\n');
+        }
+        (content, line_wise)
+    }
+
+    /// The selection after a click at `col` on `line`. The column is kept as
+    /// the horizontal position, so that moving up or down afterwards stays in
+    /// the clicked column. With `extend`, the primary region is extended to
+    /// the click instead of being replaced.
+    pub fn from_click_line_col(
+        &self,
+        line: usize,
+        col: usize,
+        buffer: &Buffer,
+        extend: bool,
+    ) -> Selection {
+        let offset = buffer.offset_of_line_col(line, col);
+        let horiz =
+            Some(ColPosition::Col(buffer.display_col_of_offset(offset) as f64));
+        match self.last_inserted() {
+            Some(primary) if extend => {
+                let mut selection = self.clone();
+                selection.regions[self.last_inserted] =
+                    SelRegion::new(primary.start, offset, horiz);
+                selection.dedupe_and_sort();
+                selection
+            }
+            _ => Selection {
+                regions: vec![SelRegion::new(offset, offset, horiz)],
+                last_inserted: 0,
+            },
+        }
+    }
+
+    /// The LSP text edits that replace every region with `replacement`, in
+    /// the order of the regions. A selection's regions are sorted and never
+    /// overlap, so neither do the edits.
+    pub fn to_text_edits(
+        &self,
+        buffer: &Buffer,
+        replacement: &str,
+    ) -> Vec<TextEdit> {
+        debug_assert!(self.regions.windows(2).all(|w| w[0].max() <= w[1].min()));
+        self.regions
+            .iter()
+            .filter_map(|region| {
+                Some(TextEdit {
+                    range: lsp_types::Range {
+                        start: buffer.offset_to_position(region.min())?,
+                        end: buffer.offset_to_position(region.max())?,
+                    },
+                    new_text: replacement.to_string(),
+                })
+            })
+            .collect()
+    }
+
+    /// What replacing every region with `new_text` would do, as the start and
+    /// end of each region with its current text and the text replacing it,
+    /// for previewing a replace before making it.
+    pub fn replace_preview(
+        &self,
+        buffer: &Buffer,
+        new_text: &str,
+    ) -> Vec<(usize, usize, String, String)> {
+        self.regions
+            .iter()
+            .map(|region| {
+                let (start, end) = (region.min(), region.max());
+                let old_text = buffer.slice_to_cow(start..end).to_string();
+                (start, end, old_text, new_text.to_string())
+            })
+            .collect()
+    }
+
+    /// The delta that inserts a sequence of numbers, counting from `start` by
+    /// `step`, one at each region in order. Regions that aren't carets are
+    /// replaced by their number.
+    pub fn insert_sequence_delta(
+        &self,
+        start: i64,
+        step: i64,
+        buffer: &Buffer,
+    ) -> RopeDelta {
+        let mut edits = Vec::new();
+        let mut n = start;
+        for region in &self.regions {
+            edits.push((region.min()..region.max(), n.to_string()));
+            n = n.saturating_add(step);
+        }
+        buffer.build_delta(&edits)
+    }
+
+    /// The delta that pastes `lines` at each region. When there are as many
+    /// lines as regions, each region gets its own line, in order. Otherwise
+    /// each region gets all of them, joined with the line ending of the
+    /// buffer. Regions that aren't carets are replaced.
+    pub fn distribute_paste_delta(
+        &self,
+        buffer: &Buffer,
+        lines: &[String],
+    ) -> RopeDelta {
+        let edits: Vec<_> = if lines.len() == self.regions.len() {
+            self.regions
+                .iter()
+                .zip(lines)
+                .map(|(region, line)| (region.min()..region.max(), line.clone()))
+                .collect()
+        } else {
+            let text = lines.join(buffer.line_ending().as_str());
+            self.regions
+                .iter()
+                .map(|region| (region.min()..region.max(), text.clone()))
+                .collect()
+        };
+        buffer.build_delta(&edits)
+    }
+
+    /// Add `delta` to the number at or after each cursor on its line, like
+    /// Ctrl-A and Ctrl-X in Vim. Decimal numbers keep their sign and leading
+    /// zeros, and `0x` hex numbers their width. Returns the edit and the
+    /// selection after it, with a caret on the last digit of each number.
+    pub fn increment_delta(
+        &self,
+        buffer: &Buffer,
+        delta: i64,
+    ) -> (RopeDelta, Selection) {
+        let mut edits = Vec::new();
+        let mut carets = Vec::new();
+        // How much the edits so far moved the text after them
+        let mut shift = 0isize;
+        let mut last_number = None;
+        for region in &self.regions {
+            let line = buffer.line_of_offset(region.end);
+            let line_start = buffer.offset_of_line(line);
+            let line_end = buffer.line_end_offset(line, true);
+            let content = buffer.slice_to_cow(line_start..line_end);
+            let number = find_number(&content, region.end - line_start).and_then(
+                |(start, end)| {
+                    let new_number = increment_number(&content[start..end], delta)?;
+                    Some((start, end, new_number))
+                },
+            );
+            let (start, end, new_number) = match number {
+                Some(number) => number,
+                None => {
+                    carets.push((region.end as isize + shift) as usize);
+                    continue;
+                }
+            };
+            let (start, end) = (line_start + start, line_start + end);
+
+            // Carets on the same number share its edit, so they are placed
+            // with the shift from before that edit
+            let (number_shift, new_edit) = match last_number {
+                Some((last_start, last_shift)) if last_start == start => {
+                    (last_shift, false)
+                }
+                _ => (shift, true),
+            };
+            carets.push(
+                (start as isize + number_shift) as usize + new_number.len() - 1,
+            );
+            if new_edit {
+                last_number = Some((start, shift));
+                shift += new_number.len() as isize - (end - start) as isize;
+                edits.push((start..end, new_number));
+            }
+        }
+
+        let mut selection = Selection::new();
+        for caret in carets {
+            selection.add_region(SelRegion::caret(caret));
+        }
+        (buffer.build_delta(&edits), selection)
+    }
+
+    /// The ranges removed by a backspace, one for each region: the grapheme
+    /// before a caret, or the region itself when it isn't a caret.
+    pub fn backspace_range(&self, buffer: &Buffer) -> Vec<(usize, usize)> {
+        self.delete_ranges(|offset| {
+            (buffer.move_left(offset, Mode::Insert, 1), offset)
+        })
+    }
+
+    /// Same as [`Selection::backspace_range`], but a caret in the indentation
+    /// of a line deletes back to the previous multiple of `indent_width`
+    /// columns, so that spaces used as a tab are removed all at once.
+    pub fn smart_backspace_range(
+        &self,
+        buffer: &Buffer,
+        indent_width: usize,
+    ) -> Vec<(usize, usize)> {
+        self.delete_ranges(|offset| {
+            let (line, col) = buffer.offset_to_line_col(offset);
+            let nonblank = buffer.first_non_blank_character_on_line(line);
+            let count = if offset <= nonblank && col > 0 && indent_width > 0 {
+                match col % indent_width {
+                    0 => indent_width,
+                    r => r,
+                }
+            } else {
+                1
+            };
+            (buffer.move_left(offset, Mode::Insert, count), offset)
+        })
+    }
+
+    /// The ranges removed by a forward delete, one for each region: the
+    /// grapheme after a caret, or the region itself when it isn't a caret.
+    pub fn delete_forward_range(&self, buffer: &Buffer) -> Vec<(usize, usize)> {
+        self.delete_ranges(|offset| {
+            (offset, buffer.move_right(offset, Mode::Insert, 1))
+        })
+    }
+
+    fn delete_ranges(
+        &self,
+        caret_range: impl Fn(usize) -> (usize, usize),
+    ) -> Vec<(usize, usize)> {
+        self.regions
+            .iter()
+            .map(|region| {
+                if region.is_caret() {
+                    caret_range(region.end)
+                } else {
+                    (region.min(), region.max())
+                }
+            })
+            .collect()
+    }
+
+    /// Select the whole lines each region is on, with or without the newline
+    /// at the end. Regions on the same or adjacent lines become one region.
+    pub fn select_current_line(
+        &self,
+        buffer: &Buffer,
+        include_newline: bool,
+    ) -> Selection {
+        let line_region = |start_line: usize, end_line: usize| {
+            let start = buffer.offset_of_line(start_line);
+            let end = if include_newline {
+                buffer.offset_of_line(end_line + 1)
+            } else {
+                buffer.line_end_offset(end_line, true)
+            };
+            SelRegion::new(start, end, None)
+        };
+
+        let mut selection = Selection::new();
+        let mut lines: Option<(usize, usize)> = None;
+        for region in &self.regions {
+            let start_line = buffer.line_of_offset(region.min());
+            let end_line = buffer.line_of_offset(region.max());
+            lines = match lines {
+                Some((first, last)) if start_line <= last + 1 => {
+                    Some((first, last.max(end_line)))
+                }
+                Some((first, last)) => {
+                    selection.add_region(line_region(first, last));
+                    Some((start_line, end_line))
+                }
+                None => Some((start_line, end_line)),
+            };
+        }
+        if let Some((first, last)) = lines {
+            selection.add_region(line_region(first, last));
+        }
+        selection
+    }
+
+    /// Select inside the quotes around each region's cursor, or including the
+    /// quotes themselves. Regions that aren't inside quotes are kept as is.
+    pub fn select_enclosing_quotes(
+        &self,
+        buffer: &Buffer,
+        include_quotes: bool,
+    ) -> Selection {
+        let mut selection = Selection {
+            regions: self
+                .regions
+                .iter()
+                .map(|region| match buffer.find_enclosing_quotes(region.end) {
+                    Some((start, end)) if include_quotes => {
+                        SelRegion::new(start, end + 1, None)
+                    }
+                    Some((start, end)) => SelRegion::new(start + 1, end, None),
+                    None => *region,
+                })
+                .collect(),
+            last_inserted: self.last_inserted,
+        };
+        selection.dedupe_and_sort();
+        selection
+    }
+
+    /// Select every occurrence in `buffer` of the word under the primary
+    /// caret. With `whole_word`, occurrences that are only part of a longer
+    /// word are skipped. The occurrence under the caret stays the primary.
+    pub fn select_all_word_occurrences(
+        &self,
+        buffer: &Buffer,
+        whole_word: bool,
+    ) -> Selection {
+        let offset = self.get_cursor_offset();
+        let (start, end) = buffer.select_word(offset);
+        if start == end {
+            return self.clone();
+        }
+        let word = buffer.slice_to_cow(start..end);
+        let text = buffer.slice_to_cow(0..buffer.len());
+        let is_word_char = |c: Option<char>| {
+            c.map(|c| get_word_property(c) == WordProperty::Other)
+                .unwrap_or(false)
+        };
+
+        let mut selection = Selection::new();
+        for (match_start, _) in text.match_indices(word.as_ref()) {
+            let match_end = match_start + word.len();
+            if match_start == start {
+                continue;
+            }
+            if whole_word
+                && (is_word_char(text[..match_start].chars().next_back())
+                    || is_word_char(text[match_end..].chars().next()))
+            {
+                continue;
+            }
+            selection.add_region(SelRegion::new(match_start, match_end, None));
+        }
+        // Added last so that it becomes the last inserted region
+        selection.add_region(SelRegion::new(start, end, None));
+        selection
+    }
+
+    /// Turn each region into a caret at its cursor, keeping all of them.
+    /// Carets that end up in the same place are merged.
+    pub fn collapse(&self) -> Selection {
+        let mut selection = Selection {
+            regions: self
+                .regions
+                .iter()
+                .map(|region| SelRegion::new(region.end, region.end, region.horiz))
+                .collect(),
+            last_inserted: self.last_inserted,
+        };
+        selection.dedupe_and_sort();
+        selection
+    }
+
+    /// Turn each region into a caret at its start, the lower of its ends,
+    /// keeping the primary region. Unlike [`Selection::min`], a caret that
+    /// lands on the cursor of its region keeps the region's `horiz`.
+    pub fn carets_at_starts(&self) -> Selection {
+        self.carets_at(SelRegion::min)
+    }
+
+    /// Turn each region into a caret at its end, the higher of its ends,
+    /// keeping the primary region.
+    pub fn carets_at_ends(&self) -> Selection {
+        self.carets_at(SelRegion::max)
+    }
+
+    fn carets_at(&self, offset: fn(SelRegion) -> usize) -> Selection {
+        let mut selection = Selection {
+            regions: self
+                .regions
+                .iter()
+                .map(|region| {
+                    let offset = offset(*region);
+                    let horiz = if offset == region.end {
+                        region.horiz
+                    } else {
+                        None
+                    };
+                    SelRegion::new(offset, offset, horiz)
+                })
+                .collect(),
+            last_inserted: self.last_inserted,
+        };
+        // A caret at the start of a region lands on the same offset as it
+        selection.dedupe_and_sort();
+        selection
+    }
+
+    /// Drop every region but the primary one, and turn it into a caret at its
+    /// cursor.
+    pub fn collapse_to_primary(&self) -> Selection {
+        match self.last_inserted() {
+            Some(region) => Selection {
+                regions: vec![SelRegion::new(region.end, region.end, region.horiz)],
+                last_inserted: 0,
+            },
+            None => Selection::new(),
+        }
+    }
+
+    pub fn get_cursor_offset(&self) -> usize {
+        if self.is_empty() {
+            return 0;
+        }
+        self.regions[self.last_inserted].end
+    }
+
+    pub fn replace_last_inserted_region(&mut self, region: SelRegion) {
+        if self.is_empty() {
+            self.add_region(region);
+            return;
+        }
+
+        self.regions.remove(self.last_inserted);
+        self.add_region(region);
+    }
+}
+
+impl<'a> IntoIterator for &'a Selection {
+    type Item = &'a SelRegion;
+    type IntoIter = std::slice::Iter<'a, SelRegion>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl Default for Selection {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A [`SelRegion`] carrying some data of the consumer's own, like the id or
+/// color of a cursor.
+#[derive(Clone, PartialEq, Debug)]
+pub struct SelRegionWithData<T> {
+    pub region: SelRegion,
+    pub data: T,
+}
+
+/// A selection whose regions each carry some data, which follows its region
+/// through edits. When regions merge, their data is merged with a `combine`
+/// function, which gets the data of the earlier region first.
+///
+/// A plain [`Selection`] is the same as a `SelectionWithData<()>`, and they
+/// convert into each other.
+#[derive(Clone, PartialEq, Debug)]
+pub struct SelectionWithData<T> {
+    regions: Vec<SelRegionWithData<T>>,
+    last_inserted: usize,
+}
+
+impl<T> SelectionWithData<T> {
+    /// Build a selection from regions in any order, with the last one as the
+    /// primary region. Overlapping regions are merged with `combine`.
+    pub fn from_regions(
+        regions: Vec<SelRegionWithData<T>>,
+        combine: impl FnMut(T, T) -> T,
+    ) -> SelectionWithData<T> {
+        let last_inserted = regions.len().saturating_sub(1);
+        let (regions, last_inserted) =
+            merge_regions(regions, last_inserted, combine);
+        SelectionWithData {
+            regions,
+            last_inserted,
+        }
+    }
+
+    pub fn regions(&self) -> &[SelRegionWithData<T>] {
+        &self.regions
+    }
+
+    pub fn last_inserted(&self) -> Option<&SelRegionWithData<T>> {
+        self.regions.get(self.last_inserted)
+    }
+
+    /// The selection without the data.
+    pub fn selection(&self) -> Selection {
+        Selection {
+            regions: self.regions.iter().map(|r| r.region).collect(),
+            last_inserted: self.last_inserted,
+        }
+    }
+
+    /// Same as [`Selection::apply_delta`], with the data of regions that the
+    /// edit brings together merged by `combine`.
+    pub fn apply_delta(
+        &self,
+        delta: &RopeDelta,
+        after: bool,
+        drift: InsertDrift,
+        combine: impl FnMut(T, T) -> T,
+    ) -> SelectionWithData<T>
+    where
+        T: Clone,
+    {
+        let mut transformer = Transformer::new(delta);
+        let regions = self
+            .regions
+            .iter()
+            .map(|r| SelRegionWithData {
+                region: transform_region(&mut transformer, &r.region, after, drift),
+                data: r.data.clone(),
+            })
+            .collect();
+        let (regions, last_inserted) =
+            merge_regions(regions, self.last_inserted, combine);
+        SelectionWithData {
+            regions,
+            last_inserted,
+        }
+    }
+}
+
+impl From<Selection> for SelectionWithData<()> {
+    fn from(selection: Selection) -> Self {
+        SelectionWithData {
+            regions: selection
+                .regions
+                .into_iter()
+                .map(|region| SelRegionWithData { region, data: () })
+                .collect(),
+            last_inserted: selection.last_inserted,
+        }
+    }
+}
+
+impl From<SelectionWithData<()>> for Selection {
+    fn from(selection: SelectionWithData<()>) -> Self {
+        selection.selection()
+    }
+}
+
+/// Sort the regions and merge the ones that overlap or coincide, so that
+/// the selection is valid again after its regions were moved around.
+/// Returns the regions with the index of the one the primary region
+/// `primary` ended up in.
+fn merge_regions<T>(
+    regions: Vec<SelRegionWithData<T>>,
+    primary: usize,
+    mut combine: impl FnMut(T, T) -> T,
+) -> (Vec<SelRegionWithData<T>>, usize) {
+    if regions.is_empty() {
+        return (regions, 0);
+    }
+
+    let primary = primary.min(regions.len() - 1);
+    let mut sorted: Vec<(bool, SelRegionWithData<T>)> = regions
+        .into_iter()
+        .enumerate()
+        .map(|(i, region)| (i == primary, region))
+        .collect();
+    sorted.sort_by_key(|(_, r)| r.region.min());
+
+    let mut merged: Vec<SelRegionWithData<T>> = Vec::with_capacity(sorted.len());
+    let mut last_inserted = 0;
+    for (is_primary, r) in sorted {
+        match merged.pop() {
+            Some(last) if last.region.should_merge(r.region) => {
+                merged.push(SelRegionWithData {
+                    region: last.region.merge_with(r.region),
+                    data: combine(last.data, r.data),
+                });
+            }
+            Some(last) => {
+                merged.push(last);
+                merged.push(r);
+            }
+            None => merged.push(r),
+        }
+        if is_primary {
+            last_inserted = merged.len() - 1;
+        }
+    }
+
+    debug_assert!(merged.windows(2).all(|w| {
+        w[0].region.max() <= w[1].region.min()
+            && !w[0].region.should_merge(w[1].region)
+    }));
+    (merged, last_inserted)
+}
+
+fn transform_region(
+    transformer: &mut Transformer<RopeInfo>,
+    region: &SelRegion,
+    after: bool,
+    drift: InsertDrift,
+) -> SelRegion {
+    let is_caret = region.start == region.end;
+    let is_region_forward = region.start < region.end;
+
+    let (start_after, end_after) = match (drift, is_caret) {
+        (InsertDrift::Inside, false) => (!is_region_forward, is_region_forward),
+        (InsertDrift::Outside, false) => (is_region_forward, !is_region_forward),
+        _ => (after, after),
+    };
+
+    SelRegion::new(
+        transformer.transform(region.start, start_after),
+        transformer.transform(region.end, end_after),
+        None,
+    )
+}
+
+/// The byte range of the first number in `line` that ends after `col`,
+/// including a `-` sign or a `0x` prefix.
+fn find_number(line: &str, col: usize) -> Option<(usize, usize)> {
+    let bytes = line.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        let is_hex = bytes[i] == b'0'
+            && matches!(bytes.get(i + 1), Some(b'x' | b'X'))
+            && matches!(bytes.get(i + 2), Some(b) if b.is_ascii_hexdigit());
+        if is_hex || bytes[i].is_ascii_digit() {
+            let start = if !is_hex && i > 0 && bytes[i - 1] == b'-' {
+                i - 1
+            } else {
+                i
+            };
+            let mut end = if is_hex { i + 2 } else { i };
+            while end < bytes.len()
+                && (bytes[end].is_ascii_digit()
+                    || (is_hex && bytes[end].is_ascii_hexdigit()))
+            {
+                end += 1;
+            }
+            if end > col {
+                return Some((start, end));
+            }
+            i = end;
+        } else {
+            i += 1;
+        }
+    }
+    None
+}
+
+/// Add `delta` to a number found by `find_number`, keeping its format, or
+/// `None` for a number too big to parse, which is then left as it is.
+fn increment_number(number: &str, delta: i64) -> Option<String> {
+    if let Some(digits) = number
+        .strip_prefix("0x")
+        .or_else(|| number.strip_prefix("0X"))
+    {
+        let value = u64::from_str_radix(digits, 16).ok()?;
+        let value = value.wrapping_add(delta as u64);
+        let width = digits.len();
+        let new_digits = if digits.bytes().any(|b| b.is_ascii_uppercase()) {
+            format!("{value:0width$X}")
+        } else {
+            format!("{value:0width$x}")
+        };
+        return Some(format!("{}{new_digits}", &number[..2]));
+    }
+
+    let value: i64 = number.parse().ok()?;
+    let value = value.saturating_add(delta);
+    let digits = number.trim_start_matches('-');
+    // Only pad numbers that were padded to begin with
+    let width = if digits.len() > 1 && digits.starts_with('0') {
+        digits.len()
+    } else {
+        1
+    };
+    let sign = if value < 0 { "-" } else { "" };
+    Some(format!("{sign}{:0width$}", value.unsigned_abs()))
+}
+
+fn remove_n_at<T>(v: &mut Vec<T>, index: usize, n: usize) {
+    match n.cmp(&1) {
+        Ordering::Equal => {
+            v.remove(index);
+        }
+        Ordering::Greater => {
+            v.drain(index..index + n);
+        }
+        _ => (),
+    };
+}
+
+#[cfg(test)]
+mod test {
+    use lsp_types::Position;
+    use xi_rope::{Interval, Rope, RopeDelta};
+
+    use crate::{
+        buffer::Buffer,
+        cursor::ColPosition,
+        mode::Mode,
+        movement::Movement,
+        selection::{
+            InsertDrift, SelRegion, SelRegionWithData, Selection, SelectionWithData,
+        },
+    };
+
+    #[test]
+    fn test_select_all_word_occurrences() {
+        let buffer = Buffer::new("foo bar foobar foo");
+        //                      ->012345678901234567<-
+        let selection = Selection::caret(5);
+
+        let all = selection.select_all_word_occurrences(&buffer, true);
+        assert_eq!(all.regions(), &[SelRegion::new(4, 7, None)]);
+
+        let selection = Selection::caret(16);
+        let all = selection.select_all_word_occurrences(&buffer, true);
+        assert_eq!(
+            all.regions(),
+            &[SelRegion::new(0, 3, None), SelRegion::new(15, 18, None)]
+        );
+        assert_eq!(all.last_inserted(), Some(&SelRegion::new(15, 18, None)));
+
+        let all = selection.select_all_word_occurrences(&buffer, false);
+        assert_eq!(
+            all.regions(),
+            &[
+                SelRegion::new(0, 3, None),
+                SelRegion::new(8, 11, None),
+                SelRegion::new(15, 18, None)
+            ]
+        );
+        assert_eq!(all.last_inserted(), Some(&SelRegion::new(15, 18, None)));
+    }
+
+    #[test]
+    fn test_collapse() {
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::new(0, 2, None));
+        selection.add_region(SelRegion::new(9, 5, Some(ColPosition::Col(1.0))));
+        selection.add_region(SelRegion::caret(12));
+        let collapsed = selection.collapse();
+        assert_eq!(collapsed.len(), selection.len());
+        assert_eq!(
+            collapsed.regions(),
+            &[
+                SelRegion::caret(2),
+                SelRegion::new(5, 5, Some(ColPosition::Col(1.0))),
+                SelRegion::caret(12)
+            ]
+        );
+        assert_eq!(collapsed.primary(), &SelRegion::caret(12));
+
+        // Regions touching at their cursors collapse into the same caret
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::new(0, 3, None));
+        selection.add_region(SelRegion::new(6, 3, None));
+        assert_eq!(selection.collapse().regions(), &[SelRegion::caret(3)]);
+    }
+
+    #[test]
+    fn test_carets_at_starts_and_ends() {
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::new(0, 3, None));
+        selection.add_region(SelRegion::new(9, 5, Some(ColPosition::Col(1.0))));
+        selection.add_region(SelRegion::caret(12));
+        selection.add_region(SelRegion::new(3, 4, None));
+
+        let starts = selection.carets_at_starts();
+        assert_eq!(
+            starts.regions(),
+            &[
+                SelRegion::caret(0),
+                SelRegion::caret(3),
+                SelRegion::new(5, 5, Some(ColPosition::Col(1.0))),
+                SelRegion::caret(12),
+            ]
+        );
+        assert_eq!(starts.primary(), &SelRegion::caret(3));
+
+        let ends = selection.carets_at_ends();
+        assert_eq!(
+            ends.regions(),
+            &[
+                SelRegion::caret(3),
+                SelRegion::caret(4),
+                SelRegion::caret(9),
+                SelRegion::caret(12),
+            ]
+        );
+        assert_eq!(ends.primary(), &SelRegion::caret(4));
+    }
+
+    #[test]
+    fn test_collapse_to_primary() {
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::new(0, 2, None));
+        selection.add_region(SelRegion::new(5, 8, None));
+        assert_eq!(
+            selection.collapse_to_primary().regions(),
+            &[SelRegion::caret(8)]
+        );
+
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::new(5, 8, None));
+        selection.add_region(SelRegion::new(2, 0, None));
+        assert_eq!(
+            selection.collapse_to_primary().regions(),
+            &[SelRegion::caret(0)]
+        );
+    }
+
+    #[test]
+    fn test_primary() {
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(9));
+        selection.add_region(SelRegion::new(1, 3, None));
+        selection.add_region(SelRegion::caret(12));
+        selection.add_region(SelRegion::new(7, 5, None));
+        assert_eq!(selection.primary_index(), 1);
+        assert_eq!(selection.primary(), &SelRegion::new(7, 5, None));
+        assert_eq!(selection.get_cursor_offset(), 5);
+
+        // The region it got merged into becomes the primary one
+        selection.add_region(SelRegion::new(2, 4, None));
+        assert_eq!(selection.primary_index(), 0);
+        assert_eq!(selection.primary(), &SelRegion::new(1, 4, None));
+        assert_eq!(
+            selection.collapse_to_primary().regions(),
+            &[SelRegion::caret(4)]
+        );
+    }
+
+    #[test]
+    fn test_apply_delta_without_merge() {
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(1));
+        selection.add_region(SelRegion::caret(2));
+
+        // Deleting the character between the carets brings them together
+        let delete = RopeDelta::simple_edit(Interval::new(1, 2), Rope::from(""), 3);
+        let merged = selection.apply_delta(&delete, true, InsertDrift::Default);
+        assert_eq!(merged.regions(), &[SelRegion::caret(1)]);
+
+        let selection =
+            selection.apply_delta_without_merge(&delete, true, InsertDrift::Default);
+        assert_eq!(
+            selection.regions(),
+            &[SelRegion::caret(1), SelRegion::caret(1)]
+        );
+
+        let insert = RopeDelta::simple_edit(Interval::new(1, 1), Rope::from("x"), 2);
+        let selection =
+            selection.apply_delta_without_merge(&insert, true, InsertDrift::Default);
+        assert_eq!(
+            selection.regions(),
+            &[SelRegion::caret(2), SelRegion::caret(2)]
+        );
+    }
+
+    #[test]
+    fn test_apply_delta_without_merge_keeps_primary() {
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(1));
+        selection.add_region(SelRegion::caret(5));
+        selection.add_region(SelRegion::caret(3));
+        assert_eq!(selection.primary(), &SelRegion::caret(3));
+
+        let insert = RopeDelta::simple_edit(Interval::new(0, 0), Rope::from("x"), 6);
+        let selection =
+            selection.apply_delta_without_merge(&insert, true, InsertDrift::Default);
+        assert_eq!(
+            selection.regions(),
+            &[
+                SelRegion::caret(2),
+                SelRegion::caret(4),
+                SelRegion::caret(6)
+            ]
+        );
+        assert_eq!(selection.primary(), &SelRegion::caret(4));
+    }
+
+    #[test]
+    fn test_apply_delta_carets() {
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(1));
+        selection.add_region(SelRegion::new(3, 5, None));
+        selection.add_region(SelRegion::caret(8));
+
+        let insert =
+            RopeDelta::simple_edit(Interval::new(2, 2), Rope::from("abc"), 10);
+        let (selection, carets) =
+            selection.apply_delta_carets(&insert, true, InsertDrift::Default);
+        assert_eq!(carets, vec![1, 8, 11]);
+        assert_eq!(
+            carets,
+            selection
+                .iter()
+                .map(|region| region.end)
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_apply_delta_merges_collapsed_regions() {
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(1));
+        selection.add_region(SelRegion::caret(8));
+        selection.add_region(SelRegion::caret(3));
+
+        // Deleting everything between the first two carets collapses them
+        let delete = RopeDelta::simple_edit(Interval::new(1, 4), Rope::from(""), 10);
+        let selection = selection.apply_delta(&delete, true, InsertDrift::Default);
+        assert_eq!(
+            selection.regions(),
+            &[SelRegion::caret(1), SelRegion::caret(5)]
+        );
+        assert_eq!(selection.last_inserted(), Some(&SelRegion::caret(1)));
+    }
+
+    #[test]
+    fn test_select_enclosing_quotes() {
+        let buffer = Buffer::new(r#"let a = "foo 'bar'"; let b = 'baz';"#);
+        //                         ->01234567890123456789012345678901234<-
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(15));
+        selection.add_region(SelRegion::caret(31));
+        selection.add_region(SelRegion::caret(22));
+
+        let inside = selection.select_enclosing_quotes(&buffer, false);
+        assert_eq!(
+            inside.regions(),
+            &[
+                SelRegion::new(9, 18, None),
+                SelRegion::caret(22),
+                SelRegion::new(30, 33, None),
+            ]
+        );
+        assert_eq!(inside.last_inserted(), Some(&SelRegion::caret(22)));
+
+        let around = selection.select_enclosing_quotes(&buffer, true);
+        assert_eq!(
+            around.regions(),
+            &[
+                SelRegion::new(8, 19, None),
+                SelRegion::caret(22),
+                SelRegion::new(29, 34, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_select_enclosing_quotes_escaped() {
+        let buffer = Buffer::new(r#"x = "say \"hi\" now" + y"#);
+        //                         ->012345678901234567890123<-
+        let selection = Selection::caret(11);
+        let inside = selection.select_enclosing_quotes(&buffer, false);
+        assert_eq!(inside.regions(), &[SelRegion::new(5, 19, None)]);
+
+        let around = selection.select_enclosing_quotes(&buffer, true);
+        assert_eq!(around.regions(), &[SelRegion::new(4, 20, None)]);
+    }
+
+    #[test]
+    fn test_backspace_range() {
+        // `e` followed by a combining acute accent is a single grapheme,
+        // which takes up the first three bytes.
+        let buffer = Buffer::new("e\u{301}x abc def");
+        let selection = Selection::caret(3);
+        assert_eq!(selection.backspace_range(&buffer), vec![(0, 3)]);
+        assert_eq!(selection.delete_forward_range(&buffer), vec![(3, 4)]);
+
+        let selection = Selection::caret(0);
+        assert_eq!(selection.backspace_range(&buffer), vec![(0, 0)]);
+        assert_eq!(selection.delete_forward_range(&buffer), vec![(0, 3)]);
+
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(3));
+        selection.add_region(SelRegion::caret(7));
+        selection.add_region(SelRegion::new(12, 9, None));
+        assert_eq!(
+            selection.backspace_range(&buffer),
+            vec![(0, 3), (6, 7), (9, 12)]
+        );
+        assert_eq!(
+            selection.delete_forward_range(&buffer),
+            vec![(3, 4), (7, 8), (9, 12)]
+        );
+    }
+
+    #[test]
+    fn test_find_overlaps() {
+        let regions = [
+            SelRegion::new(10, 15, None),
+            SelRegion::new(0, 5, None),
+            SelRegion::new(12, 20, None),
+            SelRegion::new(5, 8, None),
+            SelRegion::caret(8),
+            SelRegion::caret(30),
+            SelRegion::new(25, 30, None),
+        ];
+        assert_eq!(
+            Selection::find_overlaps(&regions),
+            vec![(0, 2), (3, 4), (5, 6)]
+        );
+
+        let regions = [SelRegion::new(0, 5, None), SelRegion::new(5, 10, None)];
+        assert!(Selection::find_overlaps(&regions).is_empty());
+    }
+
+    #[test]
+    fn test_range_over_motion() {
+        let buffer = Buffer::new("foo bar baz");
+        //                      ->01234567890<-
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(0));
+        selection.add_region(SelRegion::caret(5));
+
+        let range = |movement: Movement, count: usize| {
+            selection.range_over_motion(&movement, &buffer, count, Mode::Normal)
+        };
+        assert_eq!(range(Movement::WordForward, 1), vec![(0, 4), (5, 8)]);
+        assert_eq!(range(Movement::WordEndForward, 1), vec![(0, 3), (5, 7)]);
+        assert_eq!(range(Movement::WordForward, 2), vec![(0, 8), (5, 11)]);
+        assert_eq!(range(Movement::WordBackward, 1), vec![(0, 0), (4, 5)]);
+    }
+
+    #[test]
+    fn test_range_over_first_non_blank() {
+        let buffer = Buffer::new("    foo bar");
+        //                      ->01234567890<-
+        let range = |offset| {
+            Selection::caret(offset).range_over_motion(
+                &Movement::FirstNonBlank,
+                &buffer,
+                1,
+                Mode::Normal,
+            )
+        };
+
+        assert_eq!(range(9), vec![(4, 9)]);
+        assert_eq!(range(5), vec![(4, 5)]);
+        assert_eq!(range(4), vec![(4, 4)]);
+        assert_eq!(range(2), vec![(2, 2)]);
+        assert_eq!(range(0), vec![(0, 0)]);
+    }
+
+    #[test]
+    fn test_apply_delta_single_region() {
+        let deltas = [
+            RopeDelta::simple_edit(Interval::new(2, 2), Rope::from("xy"), 10),
+            RopeDelta::simple_edit(Interval::new(5, 5), Rope::from("xy"), 10),
+            RopeDelta::simple_edit(Interval::new(1, 4), Rope::from(""), 10),
+            RopeDelta::simple_edit(Interval::new(3, 8), Rope::from("z"), 10),
+            RopeDelta::simple_edit(Interval::new(0, 10), Rope::from(""), 10),
+        ];
+        let selections = [
+            Selection::caret(5),
+            Selection::region(3, 7),
+            Selection::region(7, 3),
+        ];
+        for selection in &selections {
+            for delta in &deltas {
+                for drift in [
+                    InsertDrift::Inside,
+                    InsertDrift::Outside,
+                    InsertDrift::Default,
+                ] {
+                    for after in [true, false] {
+                        assert_eq!(
+                            selection.apply_delta(delta, after, drift),
+                            selection.apply_delta_to_regions(delta, after, drift)
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_line_spans() {
+        // Lines start at offsets 0, 4, 10 and 13
+        let buffer = Buffer::new("abc\ndefgh\nij\nklm");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(1));
+        selection.add_region(SelRegion::new(2, 3, None));
+        selection.add_region(SelRegion::new(14, 6, None));
+
+        assert_eq!(selection.line_spans(&buffer, 0), vec![(1, 1), (2, 3)]);
+        assert_eq!(selection.line_spans(&buffer, 1), vec![(2, 6)]);
+        assert_eq!(selection.line_spans(&buffer, 2), vec![(0, 3)]);
+        assert_eq!(selection.line_spans(&buffer, 3), vec![(0, 1)]);
+
+        // A region ending at the start of a line doesn't show up on it
+        let selection = Selection::region(1, 4);
+        assert_eq!(selection.line_spans(&buffer, 0), vec![(1, 4)]);
+        assert!(selection.line_spans(&buffer, 1).is_empty());
+    }
+
+    #[test]
+    fn test_expand_to_sentences() {
+        let buffer = Buffer::new(
+            "Hello there. How are you? Fine (really).\n\nA new\nparagraph",
+        );
+        // The sentences are at 0..12, 13..25, 26..40 and 42..57
+        let selection = Selection::caret(17);
+        assert_eq!(
+            selection.expand_to_sentences(&buffer).regions(),
+            &[SelRegion::new(13, 25, None)]
+        );
+
+        // A region straddling two sentences covers both
+        let selection = Selection::region(8, 15);
+        assert_eq!(
+            selection.expand_to_sentences(&buffer).regions(),
+            &[SelRegion::new(0, 25, None)]
+        );
+
+        // Sentences don't continue over blank lines, but do over newlines
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(30));
+        selection.add_region(SelRegion::caret(50));
+        selection.add_region(SelRegion::caret(44));
+        assert_eq!(
+            selection.expand_to_sentences(&buffer).regions(),
+            &[SelRegion::new(26, 40, None), SelRegion::new(42, 57, None)]
+        );
+
+        // Carets in the space between sentences stay where they are
+        let selection = Selection::caret(41);
+        assert_eq!(
+            selection.expand_to_sentences(&buffer).regions(),
+            &[SelRegion::caret(41)]
+        );
+    }
+
+    #[test]
+    fn test_enter_visual() {
+        let buffer = Buffer::new("abc\n\nxé");
+        //                      ->0123 4 567<-
+        let mut selection = Selection::new();
+        for offset in [1, 3, 4, 6] {
+            selection.add_region(SelRegion::caret(offset));
+        }
+
+        assert_eq!(
+            selection.enter_visual(&buffer).regions(),
+            &[
+                SelRegion::new(1, 2, None),
+                SelRegion::caret(3),
+                SelRegion::caret(4),
+                SelRegion::new(6, 8, None),
+            ]
+        );
+
+        let selection = Selection::caret(8);
+        assert_eq!(
+            selection.enter_visual(&buffer).regions(),
+            &[SelRegion::caret(8)]
+        );
+
+        let selection = Selection::region(2, 0);
+        assert_eq!(
+            selection.enter_visual(&buffer).regions(),
+            &[SelRegion::new(2, 0, None)]
+        );
+    }
+
+    #[test]
+    fn test_smart_backspace_range() {
+        let buffer = Buffer::new("        foo\n  \tbar");
+        //                      ->01234567890 12 3456<-
+        let range =
+            |offset| Selection::caret(offset).smart_backspace_range(&buffer, 4);
+
+        assert_eq!(range(8), vec![(4, 8)]);
+        assert_eq!(range(4), vec![(0, 4)]);
+        assert_eq!(range(6), vec![(4, 6)]);
+        assert_eq!(range(10), vec![(9, 10)]);
+        assert_eq!(range(0), vec![(0, 0)]);
+        assert_eq!(range(12), vec![(11, 12)]);
+        assert_eq!(range(17), vec![(16, 17)]);
+    }
+
+    #[test]
+    fn test_mirror_around() {
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(7));
+        selection.add_region(SelRegion::caret(12));
+
+        let mirrored = selection.mirror_around(10, 20);
+        assert_eq!(
+            mirrored.regions(),
+            &[
+                SelRegion::caret(7),
+                SelRegion::caret(8),
+                SelRegion::caret(12),
+                SelRegion::caret(13),
+            ]
+        );
+
+        let mirrored = selection.mirror_around(15, 20);
+        assert_eq!(
+            mirrored.regions(),
+            &[
+                SelRegion::caret(7),
+                SelRegion::caret(12),
+                SelRegion::caret(18),
+                SelRegion::caret(20),
+            ]
+        );
+
+        let mirrored = selection.mirror_around(3, 20);
+        assert_eq!(
+            mirrored.regions(),
+            &[
+                SelRegion::caret(0),
+                SelRegion::caret(7),
+                SelRegion::caret(12)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_toggle_primary_direction() {
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::new(0, 3, None));
+        selection.add_region(SelRegion::new(12, 8, Some(ColPosition::Col(2.0))));
+        selection.add_region(SelRegion::new(5, 6, None));
+
+        let toggled = selection.toggle_primary_direction();
+        assert_eq!(
+            toggled.regions(),
+            &[
+                SelRegion::new(0, 3, None),
+                SelRegion::new(6, 5, None),
+                SelRegion::new(12, 8, Some(ColPosition::Col(2.0))),
+            ]
+        );
+        assert_eq!(toggled.last_inserted(), Some(&SelRegion::new(6, 5, None)));
+        assert_eq!(
+            toggled.toggle_primary_direction().regions(),
+            selection.regions()
+        );
+    }
+
+    #[test]
+    fn test_extend_primary_by() {
+        let buffer = Buffer::new("one two three four five");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(0));
+        selection.add_region(SelRegion::caret(18));
+        selection.add_region(SelRegion::new(4, 5, None));
+
+        let extended = selection.extend_primary_by(&Movement::WordForward, &buffer);
+        assert_eq!(
+            extended.regions(),
+            &[
+                SelRegion::caret(0),
+                SelRegion::new(4, 8, None),
+                SelRegion::caret(18),
+            ]
+        );
+        assert_eq!(extended.last_inserted(), Some(&SelRegion::new(4, 8, None)));
+
+        // Growing over another region merges it into the primary.
+        let extended = extended
+            .extend_primary_by(&Movement::WordForward, &buffer)
+            .extend_primary_by(&Movement::WordForward, &buffer);
+        assert_eq!(
+            extended.regions(),
+            &[SelRegion::caret(0), SelRegion::new(4, 19, None)]
+        );
+        assert_eq!(extended.last_inserted(), Some(&SelRegion::new(4, 19, None)));
+    }
+
+    #[test]
+    fn test_has_caret_at_end() {
+        let buffer = Buffer::new("abc\ndef");
+        assert!(Selection::caret(7).has_caret_at_end(&buffer));
+        assert!(!Selection::caret(0).has_caret_at_end(&buffer));
+        assert!(!Selection::caret(4).has_caret_at_end(&buffer));
+        assert!(!Selection::region(4, 7).has_caret_at_end(&buffer));
+        assert!(!Selection::new().has_caret_at_end(&buffer));
+
+        let mut selection = Selection::caret(0);
+        selection.add_region(SelRegion::caret(7));
+        assert!(selection.has_caret_at_end(&buffer));
+
+        assert!(SelRegion::caret(3).is_at(3));
+        assert!(!SelRegion::caret(3).is_at(4));
+        assert!(!SelRegion::new(1, 3, None).is_at(3));
+    }
+
+    #[test]
+    fn test_caret_lines() {
+        let buffer = Buffer::new("a\nbcd\ne\nf\ngh\n");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(2));
+        selection.add_region(SelRegion::caret(4));
+        selection.add_region(SelRegion::caret(11));
+
+        assert_eq!(
+            selection
+                .caret_lines(&buffer)
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec![1, 4]
+        );
+    }
+
+    #[test]
+    fn test_region_midpoints() {
+        // "é" takes up bytes 2 and 3
+        let buffer = Buffer::new("abéd efgh");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::new(0, 5, None));
+        selection.add_region(SelRegion::caret(6));
+        selection.add_region(SelRegion::new(10, 7, None));
+
+        assert_eq!(selection.region_midpoints(&buffer), vec![2, 6, 8]);
+
+        let selection = Selection::region(0, 7);
+        assert_eq!(selection.region_midpoints(&buffer), vec![2]);
+
+        // "e\u{301}" is a single grapheme taking up bytes 1 to 3
+        let buffer = Buffer::new("ae\u{301}bc");
+        let selection = Selection::region(0, 6);
+        assert_eq!(selection.region_midpoints(&buffer), vec![1]);
+    }
+
+    #[test]
+    fn test_split_at() {
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::new(20, 25, None));
+        selection.add_region(SelRegion::new(0, 10, None));
+
+        let split = selection.split_at(4);
+        assert_eq!(
+            split.regions(),
+            &[
+                SelRegion::new(0, 4, None),
+                SelRegion::new(4, 10, None),
+                SelRegion::new(20, 25, None),
+            ]
+        );
+        assert_eq!(split.last_inserted(), Some(&SelRegion::new(4, 10, None)));
+
+        // The edges of a region aren't inside of it.
+        assert_eq!(selection.split_at(0), selection);
+        assert_eq!(selection.split_at(10), selection);
+        assert_eq!(selection.split_at(15), selection);
+
+        let selection = Selection::region(10, 0);
+        let split = selection.split_at(4);
+        assert_eq!(
+            split.regions(),
+            &[SelRegion::new(4, 0, None), SelRegion::new(10, 4, None)]
+        );
+        assert_eq!(split.last_inserted(), Some(&SelRegion::new(4, 0, None)));
+    }
+
+    #[test]
+    fn test_clipboard_text() {
+        let buffer = Buffer::new("one two\nthree\nfour");
+
+        let selection = Selection::region(4, 7);
+        assert_eq!(
+            selection.clipboard_text(&buffer, &Mode::Insert),
+            ("two".to_string(), false)
+        );
+
+        let selection = Selection::region(0, 8);
+        assert_eq!(
+            selection.clipboard_text(&buffer, &Mode::VisualLine),
+            ("one two\n".to_string(), true)
+        );
+        // Only the mode makes a copy line-wise, not covering whole lines
+        assert_eq!(
+            selection.clipboard_text(&buffer, &Mode::Visual),
+            ("one two\n".to_string(), false)
+        );
+        assert_eq!(
+            selection.clipboard_text(&buffer, &Mode::Insert),
+            ("one two\n".to_string(), false)
+        );
+        let selection = Selection::region(8, 18);
+        assert_eq!(
+            selection.clipboard_text(&buffer, &Mode::Insert),
+            ("three\nfour".to_string(), false)
+        );
+        assert_eq!(
+            selection.clipboard_text(&buffer, &Mode::VisualLine),
+            ("three\nfour\n".to_string(), true)
+        );
+
+        // The last line has no newline of its own.
+        let selection = Selection::caret(16);
+        assert_eq!(
+            selection.clipboard_text(&buffer, &Mode::Insert),
+            ("four\n".to_string(), true)
+        );
+
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::new(0, 3, None));
+        selection.add_region(SelRegion::caret(10));
+        assert_eq!(
+            selection.clipboard_text(&buffer, &Mode::Insert),
+            ("one\nthree\n".to_string(), true)
+        );
+    }
+
+    #[test]
+    fn test_from_click_line_col() {
+        let buffer = Buffer::new("abcdef\nab\nabcdef");
+        //                      ->0123456 789 012345<-
+        let selection =
+            Selection::caret(0).from_click_line_col(0, 4, &buffer, false);
+        assert_eq!(
+            selection.regions(),
+            &[SelRegion::new(4, 4, Some(ColPosition::Col(4.0)))]
+        );
+
+        // Moving down goes back to the clicked column after a short line
+        let selection = Movement::Down.update_selection(
+            &selection,
+            &buffer,
+            1,
+            false,
+            Mode::Insert,
+        );
+        assert_eq!(9, selection.get_cursor_offset());
+        let selection = Movement::Down.update_selection(
+            &selection,
+            &buffer,
+            1,
+            false,
+            Mode::Insert,
+        );
+        assert_eq!(14, selection.get_cursor_offset());
+
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(12));
+        selection.add_region(SelRegion::caret(2));
+        let extended = selection.from_click_line_col(1, 1, &buffer, true);
+        assert_eq!(
+            extended.regions(),
+            &[
+                SelRegion::new(2, 8, Some(ColPosition::Col(1.0))),
+                SelRegion::caret(12),
+            ]
+        );
+        assert_eq!(extended.get_cursor_offset(), 8);
+    }
+
+    #[test]
+    fn test_to_text_edits() {
+        let buffer = Buffer::new("let foo = 1;\n// 𝄞 foo\nfoo += foo;\n");
+        let selection = buffer
+            .slice_to_cow(0..buffer.len())
+            .match_indices("foo")
+            .fold(Selection::new(), |mut selection, (offset, _)| {
+                selection.add_region(SelRegion::new(offset, offset + 3, None));
+                selection
+            });
+
+        let position = |line, character| Position { line, character };
+        let edits = selection.to_text_edits(&buffer, "bar");
+        assert_eq!(
+            edits
+                .iter()
+                .map(|edit| (edit.range.start, edit.range.end))
+                .collect::<Vec<_>>(),
+            vec![
+                (position(0, 4), position(0, 7)),
+                // "𝄞" is two UTF-16 code units
+                (position(1, 6), position(1, 9)),
+                (position(2, 0), position(2, 3)),
+                (position(2, 7), position(2, 10)),
+            ]
+        );
+        assert!(edits.iter().all(|edit| edit.new_text == "bar"));
+    }
+
+    #[test]
+    fn test_insert_sequence_delta() {
+        let buffer = Buffer::new("a\nb\nc\nxyz");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(4));
+        selection.add_region(SelRegion::caret(0));
+        selection.add_region(SelRegion::caret(2));
+        selection.add_region(SelRegion::new(6, 9, None));
+
+        let delta = selection.insert_sequence_delta(1, 1, &buffer);
+        assert_eq!("1a\n2b\n3c\n4", String::from(delta.apply(buffer.text())));
+
+        let selection = selection.apply_delta(&delta, true, InsertDrift::Default);
+        assert_eq!(
+            selection.regions(),
+            &[
+                SelRegion::caret(1),
+                SelRegion::caret(4),
+                SelRegion::caret(7),
+                SelRegion::caret(10),
+            ]
+        );
+
+        let buffer = Buffer::new("ab");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(0));
+        selection.add_region(SelRegion::caret(1));
+        selection.add_region(SelRegion::caret(2));
+        let delta = selection.insert_sequence_delta(10, -5, &buffer);
+        assert_eq!("10a5b0", String::from(delta.apply(buffer.text())));
+    }
+
+    #[test]
+    fn test_distribute_paste_delta() {
+        let buffer = Buffer::new("a\nb\nc");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(3));
+        selection.add_region(SelRegion::caret(1));
+        selection.add_region(SelRegion::new(4, 5, None));
+        let lines = ["x".to_string(), "y".to_string(), "z".to_string()];
+
+        let delta = selection.distribute_paste_delta(&buffer, &lines);
+        assert_eq!("ax\nby\nz", String::from(delta.apply(buffer.text())));
+
+        let delta = selection.distribute_paste_delta(&buffer, &lines[..2]);
+        assert_eq!(
+            "ax\ny\nbx\ny\nx\ny",
+            String::from(delta.apply(buffer.text()))
+        );
+    }
+
+    #[test]
+    fn test_increment_delta() {
+        let increment = |text: &str, offset: usize, delta: i64| {
+            let buffer = Buffer::new(text);
+            let (edit, selection) =
+                Selection::caret(offset).increment_delta(&buffer, delta);
+            (
+                String::from(edit.apply(buffer.text())),
+                selection.get_cursor_offset(),
+            )
+        };
+
+        assert_eq!(("x = 10;".to_string(), 5), increment("x = 9;", 0, 1));
+        assert_eq!(("a100b".to_string(), 3), increment("a099b", 2, 1));
+        assert_eq!(("-3".to_string(), 1), increment("-5", 0, 2));
+        assert_eq!(("y -1 z".to_string(), 3), increment("y 1 z", 0, -2));
+        assert_eq!(("v = 0x0f".to_string(), 7), increment("v = 0x10", 6, -1));
+        assert_eq!(("0XFF".to_string(), 3), increment("0XFE", 3, 1));
+        // The number has to be on the line of the cursor
+        assert_eq!(("1\nab".to_string(), 3), increment("1\nab", 3, 1));
+        // Numbers out of range are left alone
+        assert_eq!(
+            ("n = 99999999999999999999".to_string(), 0),
+            increment("n = 99999999999999999999", 0, 1)
+        );
+        assert_eq!(
+            ("0x1ffffffffffffffff".to_string(), 2),
+            increment("0x1ffffffffffffffff", 2, 1)
+        );
+
+        let buffer = Buffer::new("1 9\n99");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(0));
+        selection.add_region(SelRegion::caret(1));
+        selection.add_region(SelRegion::caret(4));
+        let (edit, selection) = selection.increment_delta(&buffer, 1);
+        assert_eq!("2 10\n100", String::from(edit.apply(buffer.text())));
+        assert_eq!(
+            selection.regions(),
+            &[
+                SelRegion::caret(0),
+                SelRegion::caret(3),
+                SelRegion::caret(7)
+            ]
+        );
+
+        // Both carets end up on the last digit of the shared number
+        let buffer = Buffer::new("99");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(0));
+        selection.add_region(SelRegion::caret(1));
+        let (edit, selection) = selection.increment_delta(&buffer, 1);
+        assert_eq!("100", String::from(edit.apply(buffer.text())));
+        assert_eq!(selection.regions(), &[SelRegion::caret(2)]);
+    }
+
+    #[test]
+    fn test_selection_with_data() {
+        let buffer = Buffer::new("abc def ghi");
+        let selection = SelectionWithData::from_regions(
+            vec![
+                SelRegionWithData {
+                    region: SelRegion::caret(4),
+                    data: 1,
+                },
+                SelRegionWithData {
+                    region: SelRegion::caret(0),
+                    data: 2,
+                },
+                SelRegionWithData {
+                    region: SelRegion::caret(8),
+                    data: 3,
+                },
+            ],
+            |a: u32, _| a,
+        );
+        assert_eq!(
+            selection
+                .regions()
+                .iter()
+                .map(|r| (r.region.end, r.data))
+                .collect::<Vec<_>>(),
+            vec![(0, 2), (4, 1), (8, 3)]
+        );
+
+        // Inserting before a cursor moves it, and its id goes along.
+        let delta = buffer.build_delta(&[(0..0, "xy".to_string())]);
+        let moved =
+            selection.apply_delta(&delta, true, InsertDrift::Default, |a, _| a);
+        assert_eq!(
+            moved
+                .regions()
+                .iter()
+                .map(|r| (r.region.end, r.data))
+                .collect::<Vec<_>>(),
+            vec![(2, 2), (6, 1), (10, 3)]
+        );
+        assert_eq!(moved.last_inserted().map(|r| r.data), Some(3));
+
+        // Deleting "def " brings the last two cursors together, and they
+        // merge with the earlier cursor's id kept.
+        let delta = buffer.build_delta(&[(4..8, String::new())]);
+        let merged =
+            selection.apply_delta(&delta, true, InsertDrift::Default, |a, _| a);
+        assert_eq!(
+            merged
+                .regions()
+                .iter()
+                .map(|r| (r.region.end, r.data))
+                .collect::<Vec<_>>(),
+            vec![(0, 2), (4, 1)]
+        );
+        assert_eq!(merged.last_inserted().map(|r| r.data), Some(1));
+        assert_eq!(
+            merged.selection(),
+            selection
+                .selection()
+                .apply_delta(&delta, true, InsertDrift::Default)
+        );
+    }
+
+    #[test]
+    fn test_from_block() {
+        let buffer = Buffer::new("abcdef\nab\n\nabcdefgh\nabcd");
+        //                      ->0123456 789 0 123456789 0123<-
+
+        let block = Selection::from_block(&buffer, 1, 15, false);
+        assert_eq!(
+            block.regions(),
+            &[
+                SelRegion::new(1, 4, None),
+                SelRegion::new(8, 9, None),
+                SelRegion::caret(10),
+                SelRegion::new(12, 15, None),
+            ]
+        );
+        assert_eq!(block.last_inserted(), Some(&SelRegion::new(12, 15, None)));
+
+        let block = Selection::from_block(&buffer, 1, 15, true);
+        assert_eq!(
+            block.regions(),
+            &[SelRegion::new(1, 4, None), SelRegion::new(12, 15, None)]
+        );
+
+        // From the bottom right to the top left
+        let block = Selection::from_block(&buffer, 23, 6, true);
+        assert_eq!(
+            block.regions(),
+            &[
+                SelRegion::new(3, 6, None),
+                SelRegion::new(14, 17, None),
+                SelRegion::new(23, 24, None),
+            ]
+        );
+        assert_eq!(block.last_inserted(), Some(&SelRegion::new(3, 6, None)));
+
+        let block = Selection::from_block(&buffer, 2, 4, false);
+        assert_eq!(block.regions(), &[SelRegion::new(2, 4, None)]);
+    }
+
+    #[test]
+    fn test_update_all() {
+        let buffer = Buffer::new("abc\ndef\nghi");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(0));
+        selection.add_region(SelRegion::caret(5));
+        selection.add_region(SelRegion::caret(9));
+
+        let (moved, hit_edge) =
+            selection.update_all(&Movement::Left, &buffer, Mode::Normal);
+        assert!(hit_edge);
+        assert_eq!(
+            moved.regions(),
+            &[
+                SelRegion::caret(0),
+                SelRegion::caret(4),
+                SelRegion::caret(8)
+            ]
+        );
+
+        let (_, hit_edge) =
+            selection.update_all(&Movement::Right, &buffer, Mode::Normal);
+        assert!(!hit_edge);
+
+        // The regions are extended in the Visual modes
+        for mode in [Mode::Visual, Mode::VisualBlock] {
+            let (moved, _) = selection.update_all(&Movement::Right, &buffer, mode);
+            let ranges: Vec<(usize, usize)> = moved
+                .regions()
+                .iter()
+                .map(|region| (region.start, region.end))
+                .collect();
+            assert_eq!(ranges, vec![(0, 1), (5, 6), (9, 10)], "{mode:?}");
+        }
+    }
+
+    #[test]
+    fn test_replace_preview() {
+        let buffer = Buffer::new("let foo = bar;");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::new(7, 4, None));
+        selection.add_region(SelRegion::new(10, 13, None));
+
+        assert_eq!(
+            selection.replace_preview(&buffer, "baz"),
+            vec![
+                (4, 7, "foo".to_string(), "baz".to_string()),
+                (10, 13, "bar".to_string(), "baz".to_string()),
+            ]
+        );
+        assert!(Selection::new().replace_preview(&buffer, "baz").is_empty());
+    }
+
+    #[test]
+    fn test_reveal_range() {
+        let buffer = Buffer::new("abc\ndef\nghi\njkl");
+        //                      ->0123 4567 8901 234<-
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(13));
+        selection.add_region(SelRegion::new(5, 6, None));
+        assert_eq!(selection.reveal_range(&buffer, 10), (1, 1));
+
+        selection.add_region(SelRegion::new(10, 1, None));
+        assert_eq!(selection.reveal_range(&buffer, 10), (0, 2));
+        assert_eq!(selection.reveal_range(&buffer, 3), (0, 2));
+        assert_eq!(Selection::new().reveal_range(&buffer, 10), (0, 0));
+    }
+
+    #[test]
+    fn test_reveal_range_taller_than_view() {
+        let buffer = Buffer::new("abc\ndef\nghi\njkl");
+        //                      ->0123 4567 8901 234<-
+        let selection = Selection::region(10, 1);
+        assert_eq!(selection.reveal_range(&buffer, 2), (0, 0));
+
+        let selection = Selection::region(1, 14);
+        assert_eq!(selection.reveal_range(&buffer, 2), (3, 3));
+    }
+
+    #[test]
+    fn test_flat_map_regions() {
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::new(10, 14, None));
+        selection.add_region(SelRegion::new(0, 4, None));
+
+        // Split every region in half, growing the second half a bit
+        let split = selection.flat_map_regions(|region| {
+            let mid = (region.min() + region.max()) / 2;
+            [
+                SelRegion::new(region.min(), mid, None),
+                SelRegion::new(mid, region.max() + 3, None),
+            ]
+        });
+        assert_eq!(
+            split.regions(),
+            &[
+                SelRegion::new(0, 2, None),
+                SelRegion::new(2, 7, None),
+                SelRegion::new(10, 12, None),
+                SelRegion::new(12, 17, None),
+            ]
+        );
+        assert_eq!(split.last_inserted(), Some(&SelRegion::new(2, 7, None)));
+
+        // Grown into each other
+        let merged = selection.flat_map_regions(|region| {
+            [SelRegion::new(region.min(), region.max() + 8, None)]
+        });
+        assert_eq!(merged.regions(), &[SelRegion::new(0, 22, None)]);
+
+        let removed = selection.flat_map_regions(|_| None);
+        assert!(removed.is_empty());
+    }
+
+    #[test]
+    fn test_subtract_ranges() {
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::new(0, 10, None));
+        selection.add_region(SelRegion::new(12, 15, None));
+        selection.add_region(SelRegion::new(18, 25, None));
+
+        let selection = selection.subtract_ranges(&[(4, 6), (11, 16), (20, 22)]);
+        assert_eq!(
+            selection.regions(),
+            &[
+                SelRegion::new(0, 4, None),
+                SelRegion::new(6, 10, None),
+                SelRegion::new(18, 20, None),
+                SelRegion::new(22, 25, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_subtract_region() {
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::new(0, 10, None));
+        selection.add_region(SelRegion::caret(12));
+        selection.add_region(SelRegion::new(20, 14, None));
+
+        // Split in the middle, keeping the direction
+        selection.subtract_region(3, 5);
+        assert_eq!(
+            selection.regions(),
+            &[
+                SelRegion::new(0, 3, None),
+                SelRegion::new(5, 10, None),
+                SelRegion::caret(12),
+                SelRegion::new(20, 14, None),
+            ]
+        );
+        assert_eq!(
+            selection.last_inserted(),
+            Some(&SelRegion::new(20, 14, None))
+        );
+
+        // Trims the ends, and drops the caret
+        selection.subtract_region(8, 16);
+        assert_eq!(
+            selection.regions(),
+            &[
+                SelRegion::new(0, 3, None),
+                SelRegion::new(5, 8, None),
+                SelRegion::new(20, 16, None),
+            ]
+        );
+        assert_eq!(
+            selection.last_inserted(),
+            Some(&SelRegion::new(20, 16, None))
+        );
+
+        // The primary is removed, so the one before it takes over
+        selection.subtract_region(16, 20);
+        assert_eq!(
+            selection.regions(),
+            &[SelRegion::new(0, 3, None), SelRegion::new(5, 8, None)]
+        );
+        assert_eq!(selection.last_inserted(), Some(&SelRegion::new(5, 8, None)));
+
+        // A caret at the end of the range stays
+        let mut selection = Selection::caret(4);
+        selection.subtract_region(2, 4);
+        assert_eq!(selection.regions(), &[SelRegion::caret(4)]);
+        selection.subtract_region(4, 5);
+        assert!(selection.is_empty());
+    }
+
+    #[test]
+    fn test_intersect_range() {
+        let horiz = Some(ColPosition::Col(2.0));
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::new(0, 4, None));
+        selection.add_region(SelRegion::new(6, 12, horiz));
+        selection.add_region(SelRegion::caret(14));
+        selection.add_region(SelRegion::new(24, 16, horiz));
+        selection.add_region(SelRegion::new(26, 30, None));
+
+        let clipped = selection.intersect_range(8, 20);
+        assert_eq!(
+            clipped.regions(),
+            &[
+                SelRegion::new(8, 12, horiz),
+                SelRegion::caret(14),
+                SelRegion::new(20, 16, horiz),
+            ]
+        );
+        assert_eq!(
+            clipped.last_inserted(),
+            Some(&SelRegion::new(20, 16, horiz))
+        );
+
+        let clipped = selection.intersect_range(10, 30);
+        assert_eq!(
+            clipped.regions(),
+            &[
+                SelRegion::new(10, 12, horiz),
+                SelRegion::caret(14),
+                SelRegion::new(24, 16, horiz),
+                SelRegion::new(26, 30, None),
+            ]
+        );
+        assert_eq!(clipped.last_inserted(), Some(&SelRegion::new(26, 30, None)));
+
+        // The cursor is cut off, and with it the horiz
+        assert_eq!(
+            selection.intersect_range(0, 10).regions(),
+            &[SelRegion::new(0, 4, None), SelRegion::new(6, 10, None)]
+        );
+        assert!(selection.intersect_range(4, 6).is_empty());
+        assert!(selection.intersect_range(8, 8).is_empty());
+    }
+
+    #[test]
+    fn test_snap_to_graphemes() {
+        // "e\u{301}" is an "e" with a combining accent, 3 bytes at 1..4, and
+        // the family emoji is 3 emojis joined by two ZWJs, 18 bytes at 5..23
+        let buffer =
+            Buffer::new("ae\u{301}b\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}c");
+
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::new(0, 2, None));
+        selection.add_region(SelRegion::caret(3));
+        selection.add_region(SelRegion::new(15, 9, None));
+        selection.add_region(SelRegion::new(4, 5, None));
+        let snapped = selection.snap_to_graphemes(&buffer);
+        assert_eq!(
+            snapped.regions(),
+            &[
+                // The caret at 3 snapped to 1, inside of this one
+                SelRegion::new(0, 4, None),
+                SelRegion::new(4, 5, None),
+                SelRegion::new(23, 5, None),
+            ]
+        );
+        assert_eq!(snapped.last_inserted(), Some(&SelRegion::new(4, 5, None)));
+
+        // Inside the emoji, even in the middle of a codepoint
+        let snapped = Selection::region(7, 13).snap_to_graphemes(&buffer);
+        assert_eq!(snapped.regions(), &[SelRegion::new(5, 23, None)]);
+        let snapped = Selection::caret(23).snap_to_graphemes(&buffer);
+        assert_eq!(snapped.regions(), &[SelRegion::caret(23)]);
+    }
+
+    #[test]
+    fn test_serde_round_trip() {
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::new(0, 3, Some(ColPosition::Col(2.5))));
+        selection.add_region(SelRegion::new(9, 5, Some(ColPosition::End)));
+        selection.add_region(SelRegion::caret(12));
+        selection.add_region(SelRegion::new(
+            10,
+            11,
+            Some(ColPosition::FirstNonBlank),
+        ));
+
+        let json = serde_json::to_string(&selection).unwrap();
+        assert_eq!(
+            json,
+            concat!(
+                r#"{"regions":[{"start":0,"end":3,"horiz":{"Col":2.5}},"#,
+                r#"{"start":9,"end":5,"horiz":"End"},"#,
+                r#"{"start":10,"end":11,"horiz":"FirstNonBlank"},"#,
+                r#"{"start":12,"end":12,"horiz":null}],"last_inserted":2}"#,
+            )
+        );
+        let round_trip: Selection = serde_json::from_str(&json).unwrap();
+        assert_eq!(round_trip, selection);
+        assert_eq!(round_trip.last_inserted(), selection.last_inserted());
+    }
+
+    #[test]
+    fn test_primary_visual_column() {
+        // The second line starts at 2, with "c" at 5 and "d" at 9
+        let buffer = Buffer::new("a\n\tb\tc中d");
+        let mut selection = Selection::caret(0);
+        selection.add_region(SelRegion::caret(4));
+        assert_eq!(buffer.offset_to_line_col(4), (1, 2));
+        assert_eq!(selection.primary_visual_column(&buffer, 4), 5);
+        assert_eq!(selection.primary_visual_column(&buffer, 8), 9);
+
+        // Tabs stop at the next multiple of the tab width
+        assert_eq!(Selection::caret(5).primary_visual_column(&buffer, 4), 8);
+        assert_eq!(Selection::caret(9).primary_visual_column(&buffer, 4), 11);
+        assert_eq!(Selection::new().primary_visual_column(&buffer, 4), 0);
+    }
+
+    #[test]
+    fn test_iter() {
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::new(6, 8, None));
+        selection.add_region(SelRegion::caret(2));
+        selection.add_region(SelRegion::new(4, 3, None));
+
+        let mut carets = Vec::new();
+        for region in &selection {
+            if region.is_caret() {
+                carets.push(region.end);
+            }
+        }
+        assert_eq!(carets, vec![2]);
+        assert_eq!(
+            selection
+                .iter()
+                .map(|region| region.max())
+                .collect::<Vec<_>>(),
+            vec![2, 4, 8]
+        );
+        assert!(selection.iter().eq(selection.regions()));
+    }
+
+    #[test]
+    fn test_select_all() {
+        let buffer = Buffer::new("abc\ndefg");
+        let selection = Selection::select_all(&buffer);
+        assert_eq!(
+            selection.regions(),
+            &[SelRegion::new(0, 8, Some(ColPosition::Col(4.0)))]
+        );
+
+        let buffer = Buffer::new("");
+        let selection = Selection::select_all(&buffer);
+        assert_eq!(
+            selection.regions(),
+            &[SelRegion::new(0, 0, Some(ColPosition::Col(0.0)))]
+        );
+    }
+
+    #[test]
+    fn test_min_and_max_offset() {
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::new(9, 6, None));
+        selection.add_region(SelRegion::new(1, 3, None));
+        selection.add_region(SelRegion::caret(12));
+
+        assert_eq!(selection.min_offset(), 1);
+        assert_eq!(selection.max_offset(), 12);
+        assert_eq!(
+            selection.min().regions(),
+            &[
+                SelRegion::caret(1),
+                SelRegion::caret(6),
+                SelRegion::caret(12)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_add_cursor_vertical() {
+        //                        0123456 789 012345
+        let buffer = Buffer::new("abcdef\nab\nabcdef");
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(4));
+        selection.add_region(SelRegion::caret(14));
+        selection.add_region(SelRegion::caret(8));
+
+        let above = selection.add_cursor_vertical(&buffer, true);
+        assert_eq!(
+            above.regions(),
+            &[
+                SelRegion::new(1, 1, Some(ColPosition::Col(1.0))),
+                SelRegion::caret(4),
+                SelRegion::caret(8),
+                SelRegion::new(9, 9, Some(ColPosition::Col(4.0))),
+                SelRegion::caret(14),
+            ]
+        );
+        assert_eq!(
+            above.primary(),
+            &SelRegion::new(1, 1, Some(ColPosition::Col(1.0)))
+        );
+
+        let below = selection.add_cursor_vertical(&buffer, false);
+        assert_eq!(
+            below.regions(),
+            &[
+                SelRegion::caret(4),
+                SelRegion::caret(8),
+                SelRegion::new(9, 9, Some(ColPosition::Col(4.0))),
+                SelRegion::new(11, 11, Some(ColPosition::Col(1.0))),
+                SelRegion::caret(14),
+            ]
+        );
+        assert_eq!(
+            below.primary(),
+            &SelRegion::new(11, 11, Some(ColPosition::Col(1.0)))
+        );
+    }
+
+    #[test]
+    fn test_contains() {
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::new(2, 5, None));
+        selection.add_region(SelRegion::caret(7));
+        selection.add_region(SelRegion::new(12, 9, None));
+
+        let covered: Vec<_> = (0..14).filter(|o| selection.contains(*o)).collect();
+        assert_eq!(covered, vec![2, 3, 4, 9, 10, 11]);
+
+        assert_eq!(selection.region_at(4), Some(&SelRegion::new(2, 5, None)));
+        assert_eq!(selection.region_at(9), Some(&SelRegion::new(12, 9, None)));
+        assert_eq!(selection.region_at(5), None);
+        assert_eq!(selection.region_at(7), None);
+        assert_eq!(selection.region_at(12), None);
+        assert_eq!(Selection::new().region_at(0), None);
+    }
+
+    #[test]
+    fn test_reverse() {
+        let horiz = Some(ColPosition::Col(3.0));
+        assert_eq!(
+            SelRegion::new(2, 5, horiz).reverse(),
+            SelRegion::new(5, 2, horiz)
+        );
+        assert_eq!(SelRegion::caret(4).reverse(), SelRegion::caret(4));
+
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::new(0, 3, None));
+        selection.add_region(SelRegion::caret(5));
+        selection.add_region(SelRegion::new(9, 7, horiz));
+        let reversed = selection.reverse_all();
+        assert_eq!(
+            reversed.regions(),
+            &[
+                SelRegion::new(3, 0, None),
+                SelRegion::caret(5),
+                SelRegion::new(7, 9, horiz),
+            ]
+        );
+        assert_eq!(reversed.primary(), &SelRegion::new(7, 9, horiz));
+        assert_eq!(reversed.reverse_all(), selection);
+    }
+
+    #[test]
+    fn test_selected_len() {
+        let buffer = Buffer::new("abcdéfghij");
+        let selection = Selection::new();
+        assert!(selection.is_empty());
+        assert_eq!(selection.len(), 0);
+        assert_eq!(selection.selected_len(&buffer), 0);
+
+        // The overlapping regions are merged into 1..7, where "é" is two
+        // bytes but a single char
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::new(1, 4, None));
+        selection.add_region(SelRegion::new(7, 3, None));
+        selection.add_region(SelRegion::caret(9));
+        selection.add_region(SelRegion::new(9, 11, None));
+        assert!(!selection.is_empty());
+        assert_eq!(selection.len(), 2);
+        assert_eq!(selection.selected_len(&buffer), 7);
+    }
+
+    #[test]
+    fn test_select_current_line() {
+        let buffer = Buffer::new("abc\ndef\nghi\njkl");
+        //                      ->0123 4567 8901 234<-
+        let selection = Selection::caret(5);
+        assert_eq!(
+            selection.select_current_line(&buffer, true).regions(),
+            &[SelRegion::new(4, 8, None)]
+        );
+        assert_eq!(
+            selection.select_current_line(&buffer, false).regions(),
+            &[SelRegion::new(4, 7, None)]
+        );
+
+        let selection = Selection::caret(13);
+        assert_eq!(
+            selection.select_current_line(&buffer, true).regions(),
+            &[SelRegion::new(12, 15, None)]
+        );
+
+        let mut selection = Selection::new();
+        selection.add_region(SelRegion::caret(1));
+        selection.add_region(SelRegion::caret(5));
+        selection.add_region(SelRegion::caret(13));
+        assert_eq!(
+            selection.select_current_line(&buffer, true).regions(),
+            &[SelRegion::new(0, 8, None), SelRegion::new(12, 15, None)]
+        );
+        assert_eq!(
+            selection.select_current_line(&buffer, false).regions(),
+            &[SelRegion::new(0, 7, None), SelRegion::new(12, 15, None)]
+        );
+    }
 }
@@ -36,21 +36,42 @@ fn is_boundary(&self) -> bool {
 
 pub struct WordCursor<'a> {
     pub(crate) inner: Cursor<'a, RopeInfo>,
+    punctuation_as_space: bool,
 }
 
 impl<'a> WordCursor<'a> {
     pub fn new(text: &'a Rope, pos: usize) -> WordCursor<'a> {
         let inner = Cursor::new(text, pos);
-        WordCursor { inner }
+        WordCursor {
+            inner,
+            punctuation_as_space: false,
+        }
+    }
+
+    /// Make [`WordCursor::next_boundary`] and [`WordCursor::prev_boundary`]
+    /// skip over punctuation like over whitespace, so that `foo.bar` is two
+    /// words with nothing in between.
+    pub fn punctuation_as_space(mut self, punctuation_as_space: bool) -> Self {
+        self.punctuation_as_space = punctuation_as_space;
+        self
+    }
+
+    fn word_property(&self, codepoint: char) -> WordProperty {
+        match get_word_property(codepoint) {
+            WordProperty::Punctuation if self.punctuation_as_space => {
+                WordProperty::Space
+            }
+            prop => prop,
+        }
     }
 
     /// Get previous boundary, and set the cursor at the boundary found.
     pub fn prev_boundary(&mut self) -> Option<usize> {
         if let Some(ch) = self.inner.prev_codepoint() {
-            let mut prop = get_word_property(ch);
+            let mut prop = self.word_property(ch);
             let mut candidate = self.inner.pos();
             while let Some(prev) = self.inner.prev_codepoint() {
-                let prop_prev = get_word_property(prev);
+                let prop_prev = self.word_property(prev);
                 if classify_boundary(prop_prev, prop).is_start() {
                     break;
                 }
@@ -63,6 +84,44 @@ pub fn prev_boundary(&mut self) -> Option<usize> {
         None
     }
 
+    /// Get the start of the previous path segment, and set the cursor there.
+    /// Segments are separated by `/` and any of the extra `separators`.
+    pub fn prev_path_segment(&mut self, separators: &[char]) -> Option<usize> {
+        let start = self.inner.pos();
+        let mut candidate = start;
+        let mut in_segment = false;
+        while let Some(prev) = self.inner.prev_codepoint() {
+            if is_path_separator(prev, separators) {
+                if in_segment {
+                    break;
+                }
+            } else {
+                in_segment = true;
+            }
+            candidate = self.inner.pos();
+        }
+        self.inner.set(candidate);
+        (candidate != start).then_some(candidate)
+    }
+
+    /// Get the start of the next path segment, and set the cursor there.
+    /// Segments are separated by `/` and any of the extra `separators`.
+    pub fn next_path_segment(&mut self, separators: &[char]) -> Option<usize> {
+        let start = self.inner.pos();
+        let mut candidate = start;
+        let mut past_separator = false;
+        while let Some(next) = self.inner.next_codepoint() {
+            if is_path_separator(next, separators) {
+                past_separator = true;
+            } else if past_separator {
+                break;
+            }
+            candidate = self.inner.pos();
+        }
+        self.inner.set(candidate);
+        (candidate != start).then_some(candidate)
+    }
+
     pub fn next_non_blank_char(&mut self) -> usize {
         let mut candidate = self.inner.pos();
         while let Some(next) = self.inner.next_codepoint() {
@@ -79,10 +138,10 @@ pub fn next_non_blank_char(&mut self) -> usize {
     /// Get next boundary, and set the cursor at the boundary found.
     pub fn next_boundary(&mut self) -> Option<usize> {
         if let Some(ch) = self.inner.next_codepoint() {
-            let mut prop = get_word_property(ch);
+            let mut prop = self.word_property(ch);
             let mut candidate = self.inner.pos();
             while let Some(next) = self.inner.next_codepoint() {
-                let prop_next = get_word_property(next);
+                let prop_next = self.word_property(next);
                 if classify_boundary(prop, prop_next).is_start() {
                     break;
                 }
@@ -183,6 +242,105 @@ pub fn previous_unmatched(&mut self, c: char) -> Option<usize> {
         None
     }
 
+    /// Find the nearest opening bracket of any kind before the cursor that
+    /// is not closed before the cursor, skipping complete pairs on the way.
+    pub fn previous_enclosing_bracket(&mut self) -> Option<usize> {
+        let mut n = 0;
+        while let Some(current) = self.inner.prev_codepoint() {
+            match matching_pair_direction(current) {
+                Some(true) if n == 0 => return Some(self.inner.pos()),
+                Some(true) => n -= 1,
+                Some(false) => n += 1,
+                None => (),
+            }
+        }
+        None
+    }
+
+    /// Find the nearest closing bracket of any kind after the cursor that is
+    /// not opened after the cursor, skipping complete pairs on the way.
+    pub fn next_enclosing_bracket(&mut self) -> Option<usize> {
+        let mut n = 0;
+        let mut pos = self.inner.pos();
+        while let Some(current) = self.inner.next_codepoint() {
+            match matching_pair_direction(current) {
+                Some(false) if n == 0 => return Some(pos),
+                Some(false) => n -= 1,
+                Some(true) => n += 1,
+                None => (),
+            }
+            pos = self.inner.pos();
+        }
+        None
+    }
+
+    /// Get the start of the next subword within the current word, and set
+    /// the cursor there. Stops at the end of the word rather than moving on
+    /// to the next one.
+    pub fn next_subword_boundary(&mut self) -> Option<usize> {
+        let pos = self.inner.pos();
+        let (start, end) = self.select_word();
+        let word = self.inner.root().slice_to_cow(start..end);
+        let next = subword_starts(&word)
+            .into_iter()
+            .map(|offset| start + offset)
+            .find(|&offset| offset > pos)
+            .unwrap_or(end);
+        self.inner.set(next);
+        Some(next)
+    }
+
+    /// Get the start of the previous subword within the current word, and set
+    /// the cursor there. Stops at the start of the word rather than moving on
+    /// to the previous one.
+    pub fn prev_subword_boundary(&mut self) -> Option<usize> {
+        let pos = self.inner.pos();
+        let (start, end) = self.select_word();
+        let word = self.inner.root().slice_to_cow(start..end);
+        let prev = subword_starts(&word)
+            .into_iter()
+            .rev()
+            .map(|offset| start + offset)
+            .find(|&offset| offset < pos)
+            .unwrap_or(start);
+        self.inner.set(prev);
+        Some(prev)
+    }
+
+    /// Get the start of the next subword, and set the cursor there. Unlike
+    /// [`WordCursor::next_subword_boundary`], this moves on to the start of
+    /// the next word once there are no more subwords in the current one.
+    pub fn next_subword_start(&mut self) -> Option<usize> {
+        let pos = self.inner.pos();
+        let (_, end) = self.select_word();
+        self.inner.set(pos);
+        let next = self.next_subword_boundary()?;
+        if next < end {
+            return Some(next);
+        }
+        self.inner.set(pos);
+        self.next_boundary()
+    }
+
+    /// Get the start of the previous subword, and set the cursor there.
+    /// Unlike [`WordCursor::prev_subword_boundary`], this moves on to the
+    /// last subword of the previous word when at the start of a word.
+    pub fn prev_subword_start(&mut self) -> Option<usize> {
+        let pos = self.inner.pos();
+        let prev = self.prev_subword_boundary()?;
+        if prev < pos {
+            return Some(prev);
+        }
+        self.inner.set(pos);
+        let word_start = self.prev_boundary()?;
+        let word = self.inner.root().slice_to_cow(word_start..pos);
+        let prev = subword_starts(&word)
+            .last()
+            .map_or(word_start, |offset| word_start + offset);
+        self.inner.set(prev);
+        Some(prev)
+    }
+
     pub fn select_word(&mut self) -> (usize, usize) {
         let initial = self.inner.pos();
         let end = self.next_code_boundary();
@@ -192,6 +350,39 @@ pub fn select_word(&mut self) -> (usize, usize) {
     }
 }
 
+/// The byte offsets in `word` where a subword starts, other than its very
+/// start: at a capital after a lowercase letter (`parse|Request`), at the
+/// last capital of an acronym (`HTTP|Request`), where letters and digits
+/// meet, and after underscores.
+fn subword_starts(word: &str) -> Vec<usize> {
+    let chars: Vec<(usize, char)> = word.char_indices().collect();
+    let mut starts = Vec::new();
+    for i in 1..chars.len() {
+        let prev = chars[i - 1].1;
+        let (offset, c) = chars[i];
+        let is_start = if prev == '_' {
+            c != '_'
+        } else if c == '_' {
+            false
+        } else if c.is_uppercase() {
+            !prev.is_uppercase()
+                || matches!(chars.get(i + 1), Some((_, next)) if next.is_lowercase())
+        } else if c.is_numeric() {
+            !prev.is_numeric()
+        } else {
+            prev.is_numeric()
+        };
+        if is_start {
+            starts.push(offset);
+        }
+    }
+    starts
+}
+
+fn is_path_separator(c: char, separators: &[char]) -> bool {
+    c == '/' || separators.contains(&c)
+}
+
 pub fn get_word_property(codepoint: char) -> WordProperty {
     if codepoint <= ' ' {
         if codepoint == '\r' {
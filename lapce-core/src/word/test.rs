@@ -83,3 +83,33 @@ fn next_boundary_failure_to_be_fixed_2() {
     // assert_eq!(cursor.next_boundary(), Some(6));
     // assert_eq!(cursor.next_boundary(), None);
 }
+
+#[test]
+fn test_subword_boundary() {
+    let buffer = Buffer::new("snake_case2x HTTPServer");
+    let mut cursor = WordCursor::new(buffer.text(), 0);
+    assert_eq!(cursor.next_subword_boundary(), Some(6));
+    assert_eq!(cursor.next_subword_boundary(), Some(10));
+    assert_eq!(cursor.next_subword_boundary(), Some(11));
+    assert_eq!(cursor.next_subword_boundary(), Some(12));
+    assert_eq!(cursor.next_subword_boundary(), Some(12));
+
+    let mut cursor = WordCursor::new(buffer.text(), buffer.len());
+    assert_eq!(cursor.prev_subword_boundary(), Some(17));
+    assert_eq!(cursor.prev_subword_boundary(), Some(13));
+    assert_eq!(cursor.prev_subword_boundary(), Some(13));
+}
+
+#[test]
+fn test_subword_start() {
+    let buffer = Buffer::new("fooBarBaz foo_bar HTTPRequest");
+    //                      ->01234567890123456789012345678<-
+    let mut cursor = WordCursor::new(buffer.text(), 0);
+    for expected in [3, 6, 10, 14, 18, 22, 29] {
+        assert_eq!(cursor.next_subword_start(), Some(expected));
+    }
+
+    for expected in [22, 18, 14, 10, 6, 3, 0] {
+        assert_eq!(cursor.prev_subword_start(), Some(expected));
+    }
+}
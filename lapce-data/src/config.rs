@@ -233,6 +233,14 @@ pub struct EditorConfig {
         desc = "Whether the multiple cursor selection only selects whole words."
     )]
     pub multicursor_whole_words: bool,
+    #[field_names(
+        desc = "Whether Left and Right follow the displayed order of right-to-left text rather than the order of the text."
+    )]
+    pub bidi_visual_motion: bool,
+    #[field_names(
+        desc = "Whether moving by words skips over punctuation like over whitespace."
+    )]
+    pub word_punctuation_as_space: bool,
 }
 
 impl EditorConfig {
@@ -20,13 +20,13 @@
     cursor::{ColPosition, Cursor, CursorMode},
     editor::{EditType, Editor},
     language::LapceLanguage,
+    layout::LineColumns,
     mode::{Mode, MotionMode},
-    movement::{LinePosition, Movement},
+    movement::Movement,
     register::{Clipboard, Register, RegisterData},
     selection::{SelRegion, Selection},
     style::line_styles,
     syntax::Syntax,
-    word::WordCursor,
 };
 use lapce_rpc::{
     buffer::BufferId,
@@ -1200,15 +1200,16 @@ pub fn do_multi_selection(
             }
             SelectCurrentLine => {
                 if let CursorMode::Insert(selection) = cursor.mode.clone() {
-                    let mut new_selection = Selection::new();
-                    for region in selection.regions() {
-                        let start_line = self.buffer.line_of_offset(region.min());
-                        let start = self.buffer.offset_of_line(start_line);
-                        let end_line = self.buffer.line_of_offset(region.max());
-                        let end = self.buffer.offset_of_line(end_line + 1);
-                        new_selection.add_region(SelRegion::new(start, end, None));
-                    }
-                    cursor.set_insert(selection);
+                    let new_selection =
+                        selection.select_current_line(&self.buffer, true);
+                    cursor.set_insert(new_selection);
+                }
+            }
+            SelectInsideQuotes => {
+                if let CursorMode::Insert(selection) = cursor.mode.clone() {
+                    let new_selection =
+                        selection.select_enclosing_quotes(&self.buffer, false);
+                    cursor.set_insert(new_selection);
                 }
             }
             SelectAllCurrent => {
@@ -1338,7 +1339,7 @@ pub fn do_multi_selection(
                 }
             }
             SelectAll => {
-                let new_selection = Selection::region(0, self.buffer.len());
+                let new_selection = Selection::select_all(&self.buffer);
                 cursor.set_insert(new_selection);
             }
         }
@@ -1949,59 +1950,6 @@ fn new_text_layout(
         TextLayoutLine { text, extra_style }
     }
 
-    pub fn line_horiz_col(
-        &self,
-        text: &mut PietText,
-        line: usize,
-        font_size: usize,
-        horiz: &ColPosition,
-        caret: bool,
-        config: &Config,
-    ) -> usize {
-        match *horiz {
-            ColPosition::Col(x) => {
-                let text_layout =
-                    self.get_text_layout(text, line, font_size, config);
-                let n = text_layout.text.hit_test_point(Point::new(x, 0.0)).idx;
-                n.min(self.buffer.line_end_col(line, caret))
-            }
-            ColPosition::End => self.buffer.line_end_col(line, caret),
-            ColPosition::Start => 0,
-            ColPosition::FirstNonBlank => {
-                self.buffer.first_non_blank_character_on_line(line)
-            }
-        }
-    }
-
-    #[allow(clippy::too_many_arguments)]
-    fn move_region(
-        &self,
-        text: &mut PietText,
-        region: &SelRegion,
-        count: usize,
-        modify: bool,
-        movement: &Movement,
-        mode: Mode,
-        view: &EditorView,
-        config: &Config,
-    ) -> SelRegion {
-        let (end, horiz) = self.move_offset(
-            text,
-            region.end,
-            region.horiz.as_ref(),
-            count,
-            movement,
-            mode,
-            view,
-            config,
-        );
-        let start = match modify {
-            true => region.start(),
-            false => end,
-        };
-        SelRegion::new(start, end, horiz)
-    }
-
     #[allow(clippy::too_many_arguments)]
     pub fn move_cursor(
         &mut self,
@@ -2038,10 +1986,10 @@ pub fn move_cursor(
                         config,
                     );
                     let (start, end) = match movement {
-                        Movement::EndOfLine | Movement::WordEndForward => {
-                            (offset, moved_new_offset)
-                        }
-                        Movement::MatchPairs => {
+                        Movement::EndOfLine
+                        | Movement::LastNonBlank
+                        | Movement::WordEndForward => (offset, moved_new_offset),
+                        Movement::MatchPairs | Movement::MatchingBracket(_) => {
                             if new_offset > offset {
                                 (offset, moved_new_offset)
                             } else {
@@ -2073,7 +2021,7 @@ pub fn move_cursor(
                     cursor.horiz.as_ref(),
                     count,
                     movement,
-                    Mode::Visual,
+                    cursor.get_mode(),
                     view,
                     config,
                 );
@@ -2114,15 +2062,24 @@ pub fn move_selection(
         view: &EditorView,
         config: &Config,
     ) -> Selection {
-        let mut new_selection = Selection::new();
-        for region in selection.regions() {
-            new_selection.add_region(self.move_region(
-                text, region, count, modify, movement, mode, view, config,
-            ));
-        }
-        new_selection
+        movement.update_selection_with(
+            selection,
+            &self.buffer,
+            count,
+            modify,
+            mode,
+            |offset, horiz, count, mode| {
+                self.move_offset(
+                    text, offset, horiz, count, movement, mode, view, config,
+                )
+            },
+        )
     }
 
+    /// Move `offset` the way [`Movement::move_offset_with_columns`] does,
+    /// with `ColPosition::Col` as the x position on the text layouts, and
+    /// with what only the editor knows: the lines `view` shows, the word
+    /// settings and the syntax tree.
     #[allow(clippy::too_many_arguments)]
     pub fn move_offset(
         &self,
@@ -2135,299 +2092,157 @@ pub fn move_offset(
         view: &EditorView,
         config: &Config,
     ) -> (usize, Option<ColPosition>) {
-        match movement {
-            Movement::Left => {
-                let new_offset = self.buffer.move_left(offset, mode, count);
-                (new_offset, None)
-            }
-            Movement::Right => {
-                let new_offset = self.buffer.move_right(offset, mode, count);
-                (new_offset, None)
-            }
-            Movement::Up => {
-                let line = self.buffer.line_of_offset(offset);
-                if line == 0 {
-                    return (offset, horiz.cloned());
+        if let Some(syntax) = self.syntax() {
+            let new_offset = match movement {
+                Movement::NextUnmatched(c) => {
+                    Some(syntax.find_tag(offset, false, &c.to_string()))
                 }
-
-                let (line, font_size) = match view {
-                    EditorView::Lens => {
-                        if let Some(syntax) = self.syntax() {
-                            let lens = &syntax.lens;
-                            let line = if count == 1 {
-                                let mut line = line - 1;
-                                loop {
-                                    if line == 0 {
-                                        break;
-                                    }
-
-                                    let line_height = lens.height_of_line(line + 1)
-                                        - lens.height_of_line(line);
-                                    if line_height == config.editor.line_height() {
-                                        break;
-                                    }
-                                    line -= 1;
-                                }
-                                line
-                            } else {
-                                line.saturating_sub(count)
-                            };
-                            let line_height = lens.height_of_line(line + 1)
-                                - lens.height_of_line(line);
-                            let font_size =
-                                if line_height == config.editor.line_height() {
-                                    config.editor.font_size
-                                } else {
-                                    config.editor.code_lens_font_size
-                                };
-
-                            (line, font_size)
-                        } else {
-                            (line.saturating_sub(count), config.editor.font_size)
-                        }
-                    }
-                    EditorView::Diff(version) => {
-                        let cursor_line = self.diff_cursor_line(version, line);
-                        let cursor_line = if cursor_line > count {
-                            cursor_line - count
-                        } else {
-                            0
-                        };
-                        (
-                            self.diff_actual_line(version, cursor_line),
-                            config.editor.font_size,
-                        )
-                    }
-                    EditorView::Normal => {
-                        (line.saturating_sub(count), config.editor.font_size)
-                    }
-                };
-
-                let horiz = horiz.cloned().unwrap_or_else(|| {
-                    ColPosition::Col(
-                        self.line_point_of_offset(text, offset, font_size, config).x,
-                    )
-                });
-                let col = self.line_horiz_col(
-                    text,
-                    line,
-                    font_size,
-                    &horiz,
-                    mode != Mode::Normal,
-                    config,
-                );
-                let new_offset = self.buffer.offset_of_line_col(line, col);
-                (new_offset, Some(horiz))
+                Movement::PreviousUnmatched(c) => {
+                    Some(syntax.find_tag(offset, true, &c.to_string()))
+                }
+                Movement::MatchPairs => Some(syntax.find_matching_pair(offset)),
+                _ => None,
+            };
+            if let Some(new_offset) = new_offset {
+                return (new_offset.unwrap_or(offset), None);
             }
-            Movement::Down => {
-                let last_line = self.buffer.last_line();
-                let line = self.buffer.line_of_offset(offset);
-
-                let (line, font_size) = match view {
-                    EditorView::Lens => {
-                        if let Some(syntax) = self.syntax() {
-                            let lens = &syntax.lens;
-                            let line = if count == 1 {
-                                let mut line = (line + 1).min(last_line);
-                                loop {
-                                    if line == last_line {
-                                        break;
-                                    }
-
-                                    let line_height = lens.height_of_line(line + 1)
-                                        - lens.height_of_line(line);
-                                    if line_height == config.editor.line_height() {
-                                        break;
-                                    }
-                                    line += 1;
-                                }
-                                line
-                            } else {
-                                line + count
-                            };
-                            let line_height = lens.height_of_line(line + 1)
-                                - lens.height_of_line(line);
-                            let font_size =
-                                if line_height == config.editor.line_height() {
-                                    config.editor.font_size
-                                } else {
-                                    config.editor.code_lens_font_size
-                                };
-
-                            (line, font_size)
-                        } else {
-                            (line + count, config.editor.font_size)
-                        }
-                    }
-                    EditorView::Diff(version) => {
-                        let cursor_line = self.diff_cursor_line(version, line);
-                        let cursor_line = cursor_line + count;
-                        (
-                            self.diff_actual_line(version, cursor_line),
-                            config.editor.font_size,
-                        )
-                    }
-                    EditorView::Normal => (line + count, config.editor.font_size),
-                };
-
-                let line = line.min(last_line);
+        }
 
-                let horiz = horiz.cloned().unwrap_or_else(|| {
-                    ColPosition::Col(
-                        self.line_point_of_offset(text, offset, font_size, config).x,
-                    )
-                });
-                let col = self.line_horiz_col(
+        match movement {
+            Movement::PageUp(_)
+            | Movement::PageDown(_)
+            | Movement::HalfPageUp(_)
+            | Movement::HalfPageDown(_) => match movement.page_step() {
+                Some((movement, lines)) => self.move_offset(
                     text,
-                    line,
-                    font_size,
-                    &horiz,
-                    mode != Mode::Normal,
+                    offset,
+                    horiz,
+                    count.saturating_mul(lines),
+                    &movement,
+                    mode,
+                    view,
                     config,
-                );
-                let new_offset = self.buffer.offset_of_line_col(line, col);
-                (new_offset, Some(horiz))
-            }
-            Movement::DocumentStart => (0, Some(ColPosition::Start)),
-            Movement::DocumentEnd => {
-                let last_offset = self
-                    .buffer
-                    .offset_line_end(self.buffer.len(), mode != Mode::Normal);
-                (last_offset, Some(ColPosition::End))
-            }
-            Movement::FirstNonBlank => {
-                let line = self.buffer.line_of_offset(offset);
-                let non_blank_offset =
-                    self.buffer.first_non_blank_character_on_line(line);
-                let start_line_offset = self.buffer.offset_of_line(line);
-                if offset > non_blank_offset {
-                    // Jump to the first non-whitespace character if we're strictly after it
-                    (non_blank_offset, Some(ColPosition::FirstNonBlank))
-                } else {
-                    // If we're at the start of the line, also jump to the first not blank
-                    if start_line_offset == offset {
-                        (non_blank_offset, Some(ColPosition::FirstNonBlank))
-                    } else {
-                        // Otherwise, jump to the start of the line
-                        (start_line_offset, Some(ColPosition::Start))
-                    }
-                }
-            }
-            Movement::StartOfLine => {
+                ),
+                None => (offset, horiz.cloned()),
+            },
+            // There is no soft wrapping, so display rows are the same as lines
+            Movement::Up
+            | Movement::Down
+            | Movement::DisplayUp
+            | Movement::DisplayDown
+                if !matches!(view, EditorView::Normal) =>
+            {
                 let line = self.buffer.line_of_offset(offset);
-                let new_offset = self.buffer.offset_of_line(line);
-                (new_offset, Some(ColPosition::Start))
-            }
-            Movement::EndOfLine => {
-                let new_offset =
-                    self.buffer.offset_line_end(offset, mode != Mode::Normal);
-                (new_offset, Some(ColPosition::End))
-            }
-            Movement::Line(position) => {
-                let line = match position {
-                    LinePosition::Line(line) => {
-                        (line - 1).min(self.buffer.last_line())
-                    }
-                    LinePosition::First => 0,
-                    LinePosition::Last => self.buffer.last_line(),
-                };
-                let font_size = if let EditorView::Lens = view {
-                    if let Some(syntax) = self.syntax() {
-                        let lens = &syntax.lens;
-                        let line_height = lens.height_of_line(line + 1)
-                            - lens.height_of_line(line);
-
-                        if line_height == config.editor.line_height() {
-                            config.editor.font_size
-                        } else {
-                            config.editor.code_lens_font_size
-                        }
-                    } else {
-                        config.editor.font_size
-                    }
+                let down =
+                    matches!(movement, Movement::Down | Movement::DisplayDown);
+                let view_line =
+                    self.view_line(line, count, down, mode, view, config);
+                let (movement, count) = if view_line < line {
+                    (Movement::Up, line - view_line)
                 } else {
-                    config.editor.font_size
+                    (Movement::Down, view_line - line)
                 };
-                let horiz = horiz.cloned().unwrap_or_else(|| {
-                    ColPosition::Col(
-                        self.line_point_of_offset(text, offset, font_size, config).x,
-                    )
-                });
-                let col = self.line_horiz_col(
-                    text,
-                    line,
-                    font_size,
-                    &horiz,
-                    mode != Mode::Normal,
-                    config,
-                );
-                let new_offset = self.buffer.offset_of_line_col(line, col);
-                (new_offset, Some(horiz))
-            }
-            Movement::Offset(offset) => {
-                let new_offset = *offset;
-                let new_offset = self
-                    .buffer
-                    .text()
-                    .prev_grapheme_offset(new_offset + 1)
-                    .unwrap();
-                (new_offset, None)
-            }
-            Movement::WordEndForward => {
-                let new_offset = self.buffer.move_n_wordends_forward(
+                movement.move_offset_with_columns(
+                    &self.buffer,
+                    &TextColumns::new(self, text, view, config),
                     offset,
+                    horiz,
                     count,
-                    mode == Mode::Insert,
-                );
-                (new_offset, None)
+                    mode,
+                )
             }
             Movement::WordForward => {
-                let new_offset = self.buffer.move_n_words_forward(offset, count);
+                let new_offset = self.buffer.move_n_words_forward_with(
+                    offset,
+                    count,
+                    config.editor.word_punctuation_as_space,
+                );
                 (new_offset, None)
             }
             Movement::WordBackward => {
-                let new_offset = self.buffer.move_n_words_backward(offset, count);
+                let new_offset = self.buffer.move_n_words_backward_with(
+                    offset,
+                    count,
+                    config.editor.word_punctuation_as_space,
+                );
                 (new_offset, None)
             }
-            Movement::NextUnmatched(c) => {
-                if let Some(syntax) = self.syntax.as_ref() {
-                    let new_offset = syntax
-                        .find_tag(offset, false, &c.to_string())
-                        .unwrap_or(offset);
-                    (new_offset, None)
+            _ => movement.move_offset_with_columns(
+                &self.buffer,
+                &TextColumns::new(self, text, view, config),
+                offset,
+                horiz,
+                count,
+                mode,
+            ),
+        }
+    }
+
+    /// The line `count` lines below `line`, or above it, as `view` shows the
+    /// lines: code lens steps over the lines it shrinks when moving by one,
+    /// and a diff also shows the lines that were deleted.
+    fn view_line(
+        &self,
+        line: usize,
+        count: usize,
+        down: bool,
+        mode: Mode,
+        view: &EditorView,
+        config: &Config,
+    ) -> usize {
+        match (view, self.syntax()) {
+            (EditorView::Lens, Some(syntax)) if count == 1 => {
+                let lens = &syntax.lens;
+                let is_shown = |line: usize| {
+                    lens.height_of_line(line + 1) - lens.height_of_line(line)
+                        == config.editor.line_height()
+                };
+                if down {
+                    let last_line = self.buffer.motion_last_line(mode).max(line);
+                    let mut line = (line + 1).min(last_line);
+                    while line < last_line && !is_shown(line) {
+                        line += 1;
+                    }
+                    line
                 } else {
-                    let new_offset = WordCursor::new(self.buffer.text(), offset)
-                        .next_unmatched(*c)
-                        .map_or(offset, |new| new - 1);
-                    (new_offset, None)
+                    let mut line = line.saturating_sub(1);
+                    while line > 0 && !is_shown(line) {
+                        line -= 1;
+                    }
+                    line
                 }
             }
-            Movement::PreviousUnmatched(c) => {
-                if let Some(syntax) = self.syntax.as_ref() {
-                    let new_offset = syntax
-                        .find_tag(offset, true, &c.to_string())
-                        .unwrap_or(offset);
-                    (new_offset, None)
+            (EditorView::Diff(version), _) => {
+                let cursor_line = self.diff_cursor_line(version, line);
+                let cursor_line = if down {
+                    cursor_line + count
                 } else {
-                    let new_offset = WordCursor::new(self.buffer.text(), offset)
-                        .previous_unmatched(*c)
-                        .unwrap_or(offset);
-                    (new_offset, None)
-                }
+                    cursor_line.saturating_sub(count)
+                };
+                self.diff_actual_line(version, cursor_line)
             }
-            Movement::MatchPairs => {
-                if let Some(syntax) = self.syntax.as_ref() {
-                    let new_offset =
-                        syntax.find_matching_pair(offset).unwrap_or(offset);
-                    (new_offset, None)
-                } else {
-                    let new_offset = WordCursor::new(self.buffer.text(), offset)
-                        .match_pairs()
-                        .unwrap_or(offset);
-                    (new_offset, None)
-                }
+            _ if down => line + count,
+            _ => line.saturating_sub(count),
+        }
+    }
+
+    /// The font size `line` is shown with in `view`, which is smaller for the
+    /// lines that code lens shrinks.
+    fn line_font_size(
+        &self,
+        line: usize,
+        view: &EditorView,
+        config: &Config,
+    ) -> usize {
+        if let (EditorView::Lens, Some(syntax)) = (view, self.syntax()) {
+            let lens = &syntax.lens;
+            let line_height =
+                lens.height_of_line(line + 1) - lens.height_of_line(line);
+            if line_height != config.editor.line_height() {
+                return config.editor.code_lens_font_size;
             }
         }
+        config.editor.font_size
     }
 
     pub fn code_action_size(
@@ -2586,3 +2401,55 @@ pub fn sticky_headers(&self, line: usize) -> Option<Vec<usize>> {
         lines
     }
 }
+
+/// Measures columns on the text layouts of a document, so that the column
+/// vertical movements keep is an x position, which lines up across lines
+/// with proportional fonts and different font sizes.
+struct TextColumns<'a> {
+    doc: &'a Document,
+    text: RefCell<&'a mut PietText>,
+    view: &'a EditorView,
+    config: &'a Config,
+}
+
+impl<'a> TextColumns<'a> {
+    fn new(
+        doc: &'a Document,
+        text: &'a mut PietText,
+        view: &'a EditorView,
+        config: &'a Config,
+    ) -> Self {
+        Self {
+            doc,
+            text: RefCell::new(text),
+            view,
+            config,
+        }
+    }
+}
+
+impl LineColumns for TextColumns<'_> {
+    fn horiz_of_offset(&self, buffer: &Buffer, offset: usize) -> f64 {
+        let line = buffer.line_of_offset(offset);
+        let font_size = self.doc.line_font_size(line, self.view, self.config);
+        self.doc
+            .line_point_of_offset(
+                &mut self.text.borrow_mut(),
+                offset,
+                font_size,
+                self.config,
+            )
+            .x
+    }
+
+    fn col_of_horiz(&self, _buffer: &Buffer, line: usize, horiz: f64) -> usize {
+        let font_size = self.doc.line_font_size(line, self.view, self.config);
+        let text_layout = self.doc.get_text_layout(
+            &mut self.text.borrow_mut(),
+            line,
+            font_size,
+            self.config,
+        );
+        text_layout.text.hit_test_point(Point::new(horiz, 0.0)).idx
+    }
+}
@@ -254,33 +254,15 @@ fn inline_find(
         direction: InlineFindDirection,
         c: &str,
     ) {
-        let offset = self.editor.cursor.offset();
-        let line = self.doc.buffer().line_of_offset(offset);
-        let line_content = self.doc.buffer().line_content(line);
-        let line_start_offset = self.doc.buffer().offset_of_line(line);
-        let index = offset - line_start_offset;
-        if let Some(new_index) = match direction {
-            InlineFindDirection::Left => line_content[..index].rfind(c),
-            InlineFindDirection::Right => {
-                if index + 1 >= line_content.len() {
-                    None
-                } else {
-                    let index = index
-                        + self.doc.buffer().next_grapheme_offset(
-                            offset,
-                            1,
-                            self.doc.buffer().offset_line_end(offset, false),
-                        )
-                        - offset;
-                    line_content[index..].find(c).map(|i| i + index)
-                }
-            }
-        } {
+        if let Some(ch) = c.chars().next() {
+            let forward = matches!(direction, InlineFindDirection::Right);
             self.run_move_command(
                 ctx,
-                &lapce_core::movement::Movement::Offset(
-                    new_index + line_start_offset,
-                ),
+                &lapce_core::movement::Movement::FindChar {
+                    ch,
+                    forward,
+                    till: false,
+                },
                 None,
                 Modifiers::empty(),
             );
@@ -1057,16 +1039,16 @@ fn jump_location_backward(&mut self, ctx: &mut EventCtx) -> Option<()> {
     fn page_move(&mut self, ctx: &mut EventCtx, down: bool, mods: Modifiers) {
         let line_height = self.config.editor.line_height() as f64;
         let lines =
-            (self.editor.size.borrow().height / line_height / 2.0).round() as usize;
-        let distance = (lines as f64) * line_height;
+            (self.editor.size.borrow().height / line_height).round() as usize;
+        let distance = ((lines / 2).max(1) as f64) * line_height;
         self.run_move_command(
             ctx,
-            if down {
-                &lapce_core::movement::Movement::Down
+            &if down {
+                lapce_core::movement::Movement::HalfPageDown(lines)
             } else {
-                &lapce_core::movement::Movement::Up
+                lapce_core::movement::Movement::HalfPageUp(lines)
             },
-            Some(lines),
+            None,
             mods,
         );
         let rect = Rect::ZERO
@@ -1387,6 +1369,19 @@ fn run_move_command(
         count: Option<usize>,
         mods: Modifiers,
     ) -> CommandExecuted {
+        let movement = match movement {
+            lapce_core::movement::Movement::Left
+                if self.config.editor.bidi_visual_motion =>
+            {
+                &lapce_core::movement::Movement::VisualLeft
+            }
+            lapce_core::movement::Movement::Right
+                if self.config.editor.bidi_visual_motion =>
+            {
+                &lapce_core::movement::Movement::VisualRight
+            }
+            _ => movement,
+        };
         if movement.is_jump() && movement != &self.editor.last_movement_new {
             if let BufferContent::File(path) = &self.editor.content {
                 self.main_split.save_jump_location(
@@ -15,6 +15,7 @@
 use indexmap::IndexMap;
 use itertools::Itertools;
 use lapce_core::mode::{Mode, Modes};
+use lapce_core::movement::MAX_MOTION_COUNT;
 
 mod keypress;
 mod loader;
@@ -267,7 +268,9 @@ fn handle_count<T: KeyPressFocus>(
         if let druid::KbKey::Character(c) = &keypress.key {
             if let Ok(n) = c.parse::<usize>() {
                 if self.count.is_some() || n > 0 {
-                    self.count = Some(self.count.unwrap_or(0) * 10 + n);
+                    let count =
+                        self.count.unwrap_or(0).saturating_mul(10).saturating_add(n);
+                    self.count = Some(count.min(MAX_MOTION_COUNT));
                     return true;
                 }
             }
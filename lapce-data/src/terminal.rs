@@ -314,7 +314,7 @@ fn run_command(
                                 term.vi_mode_cursor.point.line =
                                     term.bottommost_line();
                             }
-                            LinePosition::Line(_) => {}
+                            LinePosition::Line(_) | LinePosition::Relative(_) => {}
                         };
                     }
                     _ => (),
@@ -1095,7 +1095,7 @@ fn paint_cursor_new(
 
                                 let end_offset = data.doc.buffer().move_right(
                                     *start.max(end),
-                                    Mode::Visual,
+                                    data.editor.cursor.get_mode(),
                                     1,
                                 );
                                 let (_, end_col) =
@@ -1116,7 +1116,7 @@ fn paint_cursor_new(
                                 _ => {
                                     let end_offset = data.doc.buffer().move_right(
                                         *start.max(end),
-                                        Mode::Visual,
+                                        data.editor.cursor.get_mode(),
                                         1,
                                     );
                                     let (_, end_col) = data
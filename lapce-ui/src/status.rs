@@ -343,6 +343,10 @@ fn paint(&mut self, ctx: &mut PaintCtx, data: &LapceTabData, _env: &druid::Env)
                 Mode::Normal => ("Normal", LapceTheme::STATUS_MODAL_NORMAL),
                 Mode::Insert => ("Insert", LapceTheme::STATUS_MODAL_INSERT),
                 Mode::Visual => ("Visual", LapceTheme::STATUS_MODAL_VISUAL),
+                Mode::VisualLine => ("Visual Line", LapceTheme::STATUS_MODAL_VISUAL),
+                Mode::VisualBlock => {
+                    ("Visual Block", LapceTheme::STATUS_MODAL_VISUAL)
+                }
                 Mode::Terminal => ("Terminal", LapceTheme::STATUS_MODAL_TERMINAL),
             };
 